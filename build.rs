@@ -37,7 +37,6 @@ fn bundle_js_files(js_dir: &str, output_file: &str) -> std::io::Result<()> {
         // filter out imports HAHAHAHAHAHAHHHAHAHHA
         let filtered_content: String = content
             .lines()
-            .into_iter()
             .filter(|line| !line.starts_with("import { "))
             .collect::<Vec<&str>>()
             .join("\n");
@@ -66,7 +65,7 @@ fn collect_js_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<Vec
 
             if path.is_dir() {
                 collect_js_files(&path, files)?;
-            } else if path.extension().map_or(false, |ext| ext == "js") {
+            } else if path.extension().is_some_and(|ext| ext == "js") {
                 files.push(path);
             }
         }
@@ -68,7 +68,7 @@ async fn spawn_client(who: usize) {
     //spawn an async sender to push some more messages into the server
     let mut send_task = tokio::spawn(async move {
         loop {
-            let random_index = rand::random_range(0..CHUNK_SIZE);
+            let random_index = rand::random_range(0..DEFAULT_CHUNK_LENGTH * DEFAULT_CHUNK_LENGTH);
             let random_color = rand::random_range(0..=15);
 
             let packed_cell = PackedCell::new(random_index, random_color).unwrap();
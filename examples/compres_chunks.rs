@@ -1,39 +1,126 @@
-use std::{fs, path::PathBuf, time::Instant};
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let chunk_dir = PathBuf::from("canvas");
-    let mut files = Vec::new();
-
-    // Find all chunk files
-    for entry in fs::read_dir(chunk_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.extension().unwrap_or_default() == "chunk" {
-            files.push(path);
-        }
+use std::{path::Path, time::Instant};
+
+use futures::TryStreamExt;
+use paintplayground::{
+    chunk_db::{ChunkLoaderSaver, LIST_PAGE_SIZE, SimpleToFileSaver, list_objects},
+    types::ChunkCoordinates,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let saver = SimpleToFileSaver::new();
+
+    // Find all stored chunks
+    let coordinates: Vec<ChunkCoordinates> = list_objects(&saver, LIST_PAGE_SIZE)
+        .try_filter_map(|object| std::future::ready(Ok(object.coordinates(saver.board()))))
+        .try_collect()
+        .await
+        .map_err(|err| format!("{:?}", err))?;
+
+    // benchmark on the uncompressed chunks, not on the already compressed storage
+    let mut chunks = Vec::new();
+    for coordinates in &coordinates {
+        let chunk = saver
+            .load_chunk(*coordinates, false)
+            .await
+            .map_err(|err| format!("{:?}: {:?}", coordinates, err))?;
+        chunks.push(chunk.to_u8vec());
     }
 
     // center chunk, which is full random (after running clients on it)
-    let center = [PathBuf::from("canvas/0_0.chunk")];
+    let center: Vec<Vec<u8>> = match ChunkCoordinates::new(0, 0) {
+        Ok(center) => match saver.load_chunk(center, false).await {
+            Ok(chunk) => vec![chunk.to_u8vec()],
+            Err(_) => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    };
 
-    println!("Found {} chunk files", files.len());
+    println!("Found {} chunks", chunks.len());
+
+    // a trained dictionary from `dictionaries/`, or one trained on these chunks
+    let mut dictionaries =
+        paintplayground::compression::Dictionaries::load(Path::new("dictionaries"))?;
+    let dictionary_id = match dictionaries.ids().last() {
+        Some(id) => Some(*id),
+        None => train_dictionary(&chunks, &mut dictionaries),
+    };
 
     //  compression methods on all available chunks
-    benchmark_compression::<paintplayground::compression::GzipCompression>("Gzip", &files)?;
-    benchmark_compression::<paintplayground::compression::LZ4Compression>("LZ4", &files)?;
-    benchmark_compression::<paintplayground::compression::ZstdCompression>("Zstd", &files)?;
+    benchmark_compression::<paintplayground::compression::GzipCompression>("Gzip", &chunks)?;
+    benchmark_compression::<paintplayground::compression::LZ4Compression>("LZ4", &chunks)?;
+    benchmark_compression::<paintplayground::compression::ZstdCompression>("Zstd", &chunks)?;
+    if let Some(id) = dictionary_id {
+        let dictionary = dictionaries.get(id)?;
+        benchmark(
+            &format!("Zstd with dictionary {}", id),
+            &chunks,
+            |data| paintplayground::compression::ZstdDictCompression::compress(data, dictionary),
+            |data, expected_size| {
+                paintplayground::compression::ZstdDictCompression::decompress(
+                    data,
+                    dictionary,
+                    expected_size,
+                )
+            },
+        )?;
+    }
+    benchmark_compression::<paintplayground::compression::RleCompression>("RLE", &chunks)?;
+    benchmark_compression::<paintplayground::compression::RleZstdCompression>("RLE+Zstd", &chunks)?;
 
     // compression methods on center
     benchmark_compression::<paintplayground::compression::GzipCompression>("Gzip-center", &center)?;
     benchmark_compression::<paintplayground::compression::LZ4Compression>("LZ4-center", &center)?;
     benchmark_compression::<paintplayground::compression::ZstdCompression>("Zstd-center", &center)?;
+    benchmark_compression::<paintplayground::compression::RleCompression>("RLE-center", &center)?;
+    benchmark_compression::<paintplayground::compression::RleZstdCompression>(
+        "RLE+Zstd-center",
+        &center,
+    )?;
 
     Ok(())
 }
 
+/// Train a dictionary on the non uniform chunks, added as id 255
+fn train_dictionary(
+    chunks: &[Vec<u8>],
+    dictionaries: &mut paintplayground::compression::Dictionaries,
+) -> Option<u8> {
+    let samples: Vec<Vec<u8>> = chunks
+        .iter()
+        .filter(|data| data.iter().any(|byte| *byte != data[0]))
+        .cloned()
+        .collect();
+
+    match paintplayground::compression::train_dictionary(&samples, 16 * 1024) {
+        Ok(dictionary) => {
+            println!(
+                "Trained a dictionary of {} bytes on {} chunks, it has seen the chunks it is benchmarked on",
+                dictionary.len(),
+                samples.len()
+            );
+            dictionaries.insert(255, dictionary);
+            Some(255)
+        }
+        Err(err) => {
+            println!("Can't train a dictionary on these chunks: {}", err);
+            None
+        }
+    }
+}
+
 fn benchmark_compression<C: paintplayground::compression::Compression>(
     name: &str,
-    files: &[PathBuf],
+    chunks: &[Vec<u8>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    benchmark(name, chunks, C::compress, C::decompress)
+}
+
+fn benchmark(
+    name: &str,
+    chunks: &[Vec<u8>],
+    compress: impl Fn(&[u8]) -> Result<Vec<u8>, paintplayground::compression::CompressionError>,
+    decompress: impl Fn(&[u8], usize) -> Result<Vec<u8>, paintplayground::compression::CompressionError>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("\nTesting {} compression:", name);
 
@@ -42,21 +129,24 @@ fn benchmark_compression<C: paintplayground::compression::Compression>(
     let mut compress_time = std::time::Duration::default();
     let mut decompress_time = std::time::Duration::default();
 
-    for path in files {
-        let data = fs::read(path)?;
+    for data in chunks {
         total_original_size += data.len();
 
         // Benchmark compression
         let start = Instant::now();
-        let compressed = C::compress(&data)?;
+        let compressed = compress(data)?;
         compress_time += start.elapsed();
 
         total_compressed_size += compressed.len();
 
         // Benchmark decompression
         let start = Instant::now();
-        let _decompressed = C::decompress(&compressed, data.len())?;
+        let decompressed = decompress(&compressed, data.len())?;
         decompress_time += start.elapsed();
+
+        if decompressed != *data {
+            return Err(format!("{} does not give back the original chunk", name).into());
+        }
     }
 
     println!("Total original size: {} bytes", total_original_size);
@@ -3,4 +3,95 @@
 export function packedCell(index, color) {
 
     return BigInt(index) << 4n | BigInt(color);
-}
\ No newline at end of file
+}
+
+// the compact cell encoding: varints of ((index << bits | color) << 1 | run), a run is followed by its length
+// plain numbers instead of bitwise operators, those are 32 bit
+function pushVarint(bytes, value) {
+    while (value >= 128) {
+        bytes.push(value % 128 + 128);
+        value = Math.floor(value / 128);
+    }
+    bytes.push(value);
+}
+
+// updates sorted by index, following indices of one color are sent as a run
+export function encodeCompactCells(updates, bits) {
+    const bytes = [];
+    for (let i = 0; i < updates.length;) {
+        const { index, color } = updates[i];
+        let run = 1;
+        while (i + run < updates.length && updates[i + run].index === index + run && updates[i + run].color === color) {
+            run++;
+        }
+
+        const cell = (index * 2 ** bits + color) * 2;
+        if (run > 1) {
+            pushVarint(bytes, cell + 1);
+            pushVarint(bytes, run);
+        } else {
+            pushVarint(bytes, cell);
+        }
+        i += run;
+    }
+    return new Uint8Array(bytes);
+}
+
+// calls apply(index, colorNumber) for every cell in the bytes from offset on
+export function decodeCompactCells(bytes, offset, bits, apply) {
+    let i = offset;
+    const readVarint = () => {
+        let value = 0;
+        for (let shift = 0; i < bytes.length && shift < 70; shift += 7) {
+            const byte = bytes[i++];
+            value += (byte % 128) * 2 ** shift;
+            if (byte < 128) {
+                return value;
+            }
+        }
+        return undefined;
+    };
+
+    for (let record = readVarint(); record !== undefined; record = readVarint()) {
+        const length = record % 2 === 1 ? readVarint() : 1;
+        if (length === undefined) {
+            break;
+        }
+        const cell = Math.floor(record / 2);
+        const index = Math.floor(cell / 2 ** bits);
+        const colorNumber = cell % 2 ** bits;
+        for (let n = 0; n < length; n++) {
+            apply(index + n, colorNumber);
+        }
+    }
+}
+
+// packed pixels of run-length encoded colors (format byte 4), a run is a token of (length - 1) << 4 | color,
+// 0xF as length is followed by a varint of length - 16
+export function decodeRle(bytes, offset, byteLength) {
+    const nibbles = new Uint8Array(byteLength * 2);
+    let position = 0;
+    for (let i = offset; i < bytes.length && position < nibbles.length;) {
+        const token = bytes[i++];
+        let length = (token >> 4) + 1;
+        if (length > 15) {
+            let extra = 0;
+            for (let shift = 0; i < bytes.length; shift += 7) {
+                const byte = bytes[i++];
+                extra += (byte % 128) * 2 ** shift;
+                if (byte < 128) {
+                    break;
+                }
+            }
+            length = extra + 16;
+        }
+        nibbles.fill(token & 0x0F, position, position + length);
+        position += length;
+    }
+
+    const packed = new Uint8Array(byteLength);
+    for (let i = 0; i < byteLength; i++) {
+        packed[i] = nibbles[i * 2] << 4 | nibbles[i * 2 + 1];
+    }
+    return packed;
+}
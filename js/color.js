@@ -1,3 +1,5 @@
+import { boardPath } from './utils.js';
+
 export let selectedColor = 'one';
 export const colorMapping = {
     'zero': 0,
@@ -17,52 +19,39 @@ export const colorMapping = {
     'fourteen': 14,
     'fifteen': 15
 };
+// the woodspark palette, replaced by the board palette from /api/palette
+export let paletteColors = [
+    '#e0d3c8', '#f5eeb0', '#fabf61', '#e08d51', '#8a5865', '#452b3f', '#2c5e3b', '#609c4f',
+    '#c6cc54', '#78c2d6', '#5479b0', '#56546e', '#839fa6', '#f05b5b', '#8f325f', '#eb6c98'
+];
+// bits of a color in chunks and updates, 4 or 8
+export let paletteBits = 4;
+
 export function colorFromNumber(number) {
-    switch (number) {
-        case 0:
-            return "#e0d3c8";
-        case 1:
-            return '#f5eeb0';
-        case 2:
-            return '#fabf61';
-        case 3:
-            return '#e08d51';
-        case 4:
-            return '#8a5865';
-        case 5:
-            return '#452b3f';
-        case 6:
-            return '#2c5e3b';
-        case 7:
-            return '#609c4f';
-        case 8:
-            return '#c6cc54';
-        case 9:
-            return '#78c2d6';
-        case 10:
-            return '#5479b0';
-        case 11:
-            return '#56546e';
-        case 12:
-            return '#839fa6';
-        case 13:
-            return '#f05b5b';
-        case 14:
-            return '#8f325f';
-        case 15:
-            return '#eb6c98';
-        default:
-            return '#e0d3c8';
-    }
+    return paletteColors[number] ?? paletteColors[0];
+}
+
+function colorButtons() {
+    Object.keys(colorMapping).forEach(key => {
+        const button = document.getElementById(key);
+        if (button) {
+            button.style.backgroundColor = colorFromNumber(colorMapping[key]);
+        }
+    });
 }
+
+fetch(`${boardPath}/api/palette`)
+    .then(response => response.json())
+    .then(palette => {
+        paletteColors = palette.colors;
+        paletteBits = palette.bits;
+        colorButtons();
+    })
+    .catch(error => console.error('Could not load the palette', error));
+
 document.getElementById('color-picker').addEventListener('click', function (event) {
     selectedColor = event.target.id;
 });
 
 // Set the background color of the color buttons
-Object.keys(colorMapping).forEach(key => {
-    const button = document.getElementById(key);
-    if (button) {
-        button.style.backgroundColor = colorFromNumber(colorMapping[key]);
-    }
-});
\ No newline at end of file
+colorButtons();
\ No newline at end of file
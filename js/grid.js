@@ -46,6 +46,32 @@ export class Grid {
         this.gridContainer = gridContainer;
     }
 
+    // one box per pixel of the chunk, the server tells its size when connecting
+    resize(width, height) {
+        if (this.columns === width && this.rows === height) {
+            return;
+        }
+        this.columns = width;
+        this.rows = height;
+
+        const boxSize = 20;
+        this.gridWidth = width * boxSize;
+        this.gridHeight = height * boxSize;
+        this.gridContainer.style.width = `${this.gridWidth}px`;
+        this.gridContainer.style.height = `${this.gridHeight}px`;
+        this.gridContainer.style.maxWidth = `${this.gridWidth}px`;
+        this.gridContainer.style.maxHeight = `${this.gridHeight}px`;
+        this.gridContainer.style.gridTemplateColumns = `repeat(${width}, ${boxSize}px)`;
+
+        this.gridContainer.querySelectorAll('.gridBox').forEach(box => box.remove());
+        for (let index = 0; index < width * height; index++) {
+            const box = document.createElement('div');
+            box.id = index;
+            box.className = 'gridBox';
+            this.gridContainer.appendChild(box);
+        }
+    }
+
     clear() {
         const boxes = this.gridContainer.querySelectorAll('.gridBox');
         boxes.forEach(box => box.style.backgroundColor = 'grey');
@@ -1,12 +1,14 @@
 import { Grid } from '../js/grid.js';
 import { colorMapping } from './color.js';
 import { Ws } from './ws.js';
+import { boardPath } from './utils.js';
+import { encodeCompactCells } from './cell.js';
 
 export class ChunkManager {
     constructor(x, y) {
         this.grid = new Grid(this.appendColoringUpdate.bind(this));
 
-        this.ws = new Ws(x, y, this.applyColoringUpdate.bind(this));
+        this.ws = new Ws(x, y, this.applyColoringUpdate.bind(this), this.grid.resize.bind(this.grid));
 
         this.allowUpdates = true;
         this.updates = [];
@@ -19,11 +21,18 @@ export class ChunkManager {
 
             if (filteredUpdates.length > 0) {
                 console.log("sending", filteredUpdates.length, "updates");
+                if (this.ws.compact) {
+                    const cells = filteredUpdates.map(update => ({ index: update.index, color: colorMapping[update.color] }));
+                    this.ws.socket.send(encodeCompactCells(cells, this.ws.bits));
+                    this.updates = [];
+                    return;
+                }
+
                 const data = new Uint8Array(filteredUpdates.length * 8); // Each u64 is 8 bytes
                 const view = new DataView(data.buffer);
 
                 filteredUpdates.forEach((update, i) => {
-                    view.setBigUint64(i * 8, BigInt(update.index << 4) | BigInt(colorMapping[update.color]), true);
+                    view.setBigUint64(i * 8, (BigInt(update.index) << BigInt(this.ws.bits)) | BigInt(colorMapping[update.color]), true);
                 });
 
                 this.ws.socket.send(data.buffer);
@@ -92,7 +101,7 @@ export class ChunkManager {
     }
     changeDownloadText(x, y) {
         const downloadLink = document.getElementById("downloadLink");
-        downloadLink.href = `https://canvas.structwafel.dev/screenshot?x=${x}&y=${y}`;
+        downloadLink.href = `https://canvas.structwafel.dev${boardPath}/screenshot?x=${x}&y=${y}`;
     }
 }
 
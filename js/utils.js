@@ -1 +1,2 @@
-
+// the page of a named board is /b/{board}/, its requests go under the same prefix
+export const boardPath = (window.location.pathname.match(/^\/b\/[^/]+/) || [''])[0];
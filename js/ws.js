@@ -1,11 +1,22 @@
-import { colorFromNumber } from './color.js';
+import { colorFromNumber, paletteBits } from './color.js';
+import { boardPath } from './utils.js';
+import { decodeCompactCells, decodeRle } from './cell.js';
 
 export class Ws {
-    constructor(x, y, applyColoringUpdate) {
+    constructor(x, y, applyColoringUpdate, resizeGrid) {
         this.x = x;
         this.y = y;
 
         this.applyColor = applyColoringUpdate;
+        this.resizeGrid = resizeGrid;
+        // bits of a color, from the dimensions message
+        this.bits = paletteBits;
+        // whether the server sends and takes the compact cells, also from the dimensions message
+        this.compact = false;
+        // pixels in a chunk, from the dimensions message
+        this.pixels = 0;
+        // the server drops the updates of a chunk it failed to load
+        this.readOnly = false;
 
         this.reconnectDelay = 1000; // initial delay
 
@@ -77,30 +88,29 @@ export class Ws {
 
             case 1:
                 console.log('Received chunk');
-                for (let i = 1; i < data.byteLength; i++) {
-                    const byte = view.getUint8(i);
-                    const color1 = byte >> 4;
-                    const color2 = byte & 0x0F;
-
-                    const doublePackedColor1 = colorFromNumber(color1);
-                    const doublePackedColor2 = colorFromNumber(color2);
-
-                    this.applyColor((i - 1) * 2, doublePackedColor1);
-                    this.applyColor((i - 1) * 2 + 1, doublePackedColor2);
-                }
+                this.applyPackedPixels(new Uint8Array(data, 1));
                 break;
-            // chunks updates, a packed u64 with 60bit index and 4bit color
-            case 2:
+            // chunks updates, a packed u64 with the index and a 4 or 8 bit color, or compact cells
+            case 2: {
                 console.log('Received chunk updates');
+                if (this.compact) {
+                    decodeCompactCells(new Uint8Array(data), 1, this.bits, (index, colorNumber) => {
+                        this.applyColor(index, colorFromNumber(colorNumber));
+                    });
+                    break;
+                }
+                const bits = BigInt(this.bits);
+                const mask = (1n << bits) - 1n;
                 for (let i = 1; i < data.byteLength; i += 8) {
                     const packed = view.getBigUint64(i, true);
-                    const index = Number(packed >> 4n);
+                    const index = Number(packed >> bits);
 
-                    const colorNumber = Number(packed & 0b1111n);
+                    const colorNumber = Number(packed & mask);
 
                     this.applyColor(index, colorFromNumber(colorNumber));
                 }
-                break
+                break;
+            }
             // chunk not found
             // requested a chunk that does not exist, disconnectm
             case 3:
@@ -112,11 +122,77 @@ export class Ws {
                 alert('Too many chunks loaded, wait a bit');
                 this.socket.close();
                 break;
+            // width and height of the chunks, as u16, the bits of a color and the cell encoding, sent before the chunk
+            case 5: {
+                const width = view.getUint16(1, true);
+                const height = view.getUint16(3, true);
+                this.pixels = width * height;
+                if (data.byteLength > 5) {
+                    this.bits = view.getUint8(5);
+                }
+                // older servers don't know the compact cells
+                this.compact = data.byteLength > 6 && view.getUint8(6) === 1;
+                console.log('Chunks are', width, 'x', height, 'with', this.bits, 'bit colors');
+                if (this.resizeGrid) {
+                    this.resizeGrid(width, height);
+                }
+                break;
+            }
+            // the entire chunk as a format byte and payload, the formats asked for in getWsUrl
+            case 6: {
+                const format = view.getUint8(1);
+                console.log('Received chunk in format', format);
+                const byteLength = this.pixels * this.bits / 8;
+                if (format === 0) {
+                    this.applyPackedPixels(new Uint8Array(data, 2));
+                } else if (format === 3) {
+                    const color = colorFromNumber(view.getUint8(2));
+                    for (let i = 0; i < this.pixels; i++) {
+                        this.applyColor(i, color);
+                    }
+                } else if (format === 4) {
+                    this.applyPackedPixels(decodeRle(new Uint8Array(data), 2, byteLength));
+                } else {
+                    console.error('Unknown chunk format', format);
+                }
+                break;
+            }
+            // the chunk couldn't be loaded and drops updates until it can, or it can be painted again
+            case 7: {
+                this.readOnly = view.getUint8(1) === 1;
+                if (this.readOnly) {
+                    this.updateConnectionStatus('orange', 'Read only');
+                } else {
+                    this.updateConnectionStatus('green', 'Connected');
+                }
+                break;
+            }
             default:
                 console.error('Unknown message type');
         }
     }
 
+    // the packed pixels of an entire chunk, 2 colors in a byte or 1 with 8 bit colors
+    applyPackedPixels(bytes) {
+        if (this.bits === 8) {
+            for (let i = 0; i < bytes.length; i++) {
+                this.applyColor(i, colorFromNumber(bytes[i]));
+            }
+            return;
+        }
+        for (let i = 0; i < bytes.length; i++) {
+            const byte = bytes[i];
+            const color1 = byte >> 4;
+            const color2 = byte & 0x0F;
+
+            const doublePackedColor1 = colorFromNumber(color1);
+            const doublePackedColor2 = colorFromNumber(color2);
+
+            this.applyColor(i * 2, doublePackedColor1);
+            this.applyColor(i * 2 + 1, doublePackedColor2);
+        }
+    }
+
     updateConnectionStatus(color, text) {
         const statusDiv = document.getElementById('connection_status');
         if (statusDiv) {
@@ -127,11 +203,11 @@ export class Ws {
 }
 
 export const host = window.location.host;
-// if it is secure use wss
+// if it is secure use wss, asks for the compact cells and chunks as raw, single color or rle
 export function getWsUrl(x, y) {
     if (window.location.protocol === 'https:') {
-        return `wss://${host}/ws/${x}/${y}`;
+        return `wss://${host}${boardPath}/ws/${x}/${y}?cells=compact&formats=0,3,4`;
     } else {
-        return `ws://${host}/ws/${x}/${y}`;
+        return `ws://${host}${boardPath}/ws/${x}/${y}?cells=compact&formats=0,3,4`;
     }
 }
@@ -1,7 +1,25 @@
-use std::sync::{Arc, atomic::AtomicU64};
-
-use crate::chunk_manager::{ChunkManager, ChunkUpdate, HandlerData};
-use paintplayground::{chunk_db::ChunkLoaderSaver, types::*};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, Mutex, atomic::AtomicU64},
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::chunk_manager::{ChunkManager, ChunkStats, ChunkUpdate, HandlerData};
+use crate::config::{BoardConfig, ChunkConfig, Config};
+use crate::stats::{PixelCounter, Throughput};
+use crate::{
+    screenshot::Screenshot,
+    tiles::{self, TileBudget, TileCache, TileCoordinates},
+};
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use paintplayground::{
+    chunk_db::{ChunkLoaderSaver, ChunkMetadata},
+    types::*,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum BoardManagerError {
@@ -9,6 +27,37 @@ pub enum BoardManagerError {
     TooManyChunksLoaded,
     #[error("failed to load chunk/chunks")]
     _LoadingChunks,
+    #[error("board manager is not responding")]
+    Unresponsive,
+    #[error("storage is unreachable: {0}")]
+    StorageUnreachable(String),
+    #[error("storage failed: {0}")]
+    Storage(String),
+    #[error("chunk is being reset")]
+    Resetting,
+    #[error(transparent)]
+    Shrink(#[from] ShrinkError),
+}
+
+/// How long the health checks wait on the BoardManager before giving up
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait on a single ChunkManager when collecting stats
+const CHUNK_STATS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many chunks are listed in [`BoardStats::most_active`]
+const MOST_ACTIVE_CHUNKS: usize = 10;
+
+/// Stats of the whole board, collected from every live ChunkManager
+#[derive(Debug, Clone, Serialize)]
+pub struct BoardStats {
+    pub bounds: Bounds,
+    pub live_chunks: usize,
+    pub throughput: Throughput,
+    /// live chunks, sorted by coordinates
+    pub chunks: Vec<ChunkStats>,
+    /// live chunks with the most pixel updates in the last minute
+    pub most_active: Vec<ChunkStats>,
 }
 
 #[derive(Debug)]
@@ -32,10 +81,123 @@ pub enum BoardManagerMessage {
         ChunkCoordinates,
         oneshot::Sender<Result<HandlerData, BoardManagerError>>,
     ),
+    /// Check if the BoardManager loop is still responding
+    Ping(oneshot::Sender<()>),
+    /// Check if the storage behind the BoardManager is reachable
+    ProbeStorage(oneshot::Sender<Result<(), BoardManagerError>>),
+    /// What the storage knows about a chunk, without loading it
+    GetChunkMetadata(
+        ChunkCoordinates,
+        oneshot::Sender<Result<Option<ChunkMetadata>, BoardManagerError>>,
+    ),
+    /// Collect the stats of the board and every live chunk
+    GetStats(oneshot::Sender<BoardStats>),
+    /// Get the png of a map tile
+    GetTile(TileCoordinates, oneshot::Sender<Bytes>),
+    /// Delete the stored chunk, a live ChunkManager of it starts over blank
+    ResetChunk(
+        ChunkCoordinates,
+        oneshot::Sender<Result<(), BoardManagerError>>,
+    ),
+    /// Store bigger bounds for the board, and use them once they are stored
+    GrowBounds(Bounds, oneshot::Sender<Result<(), BoardManagerError>>),
+}
+/// How often each chunk changed since the server started
+///
+/// Used to know if something rendered from a region of chunks is outdated.
+#[derive(Debug)]
+pub struct ChunkVersions {
+    /// random per process, versions start from 0 again after a restart
+    epoch: u64,
+    versions: dashmap::DashMap<ChunkCoordinates, u64>,
+    /// when each chunk changed last
+    changed_at: dashmap::DashMap<ChunkCoordinates, SystemTime>,
+    /// counts every change, to wait for the next one
+    changes: watch::Sender<u64>,
+    board: Arc<Board>,
+}
+
+impl ChunkVersions {
+    fn new(board: Arc<Board>) -> Self {
+        Self {
+            epoch: rand::random(),
+            versions: dashmap::DashMap::new(),
+            changed_at: dashmap::DashMap::new(),
+            changes: watch::Sender::new(0),
+            board,
+        }
+    }
+
+    fn bump(&self, coordinates: ChunkCoordinates) {
+        *self.versions.entry(coordinates).or_insert(0) += 1;
+        self.changed_at.insert(coordinates, SystemTime::now());
+        self.changes.send_modify(|count| *count += 1);
+    }
+
+    /// When the chunk last changed, `None` if it didn't since the server started
+    pub fn last_change(&self, coordinates: ChunkCoordinates) -> Option<SystemTime> {
+        self.changed_at.get(&coordinates).map(|time| *time)
+    }
+
+    /// A value that changes whenever a chunk inside the region changes
+    pub fn region_version(
+        &self,
+        top_left: ChunkCoordinates,
+        bottom_right: ChunkCoordinates,
+    ) -> u64 {
+        let min_x = top_left.x().min(bottom_right.x());
+        let max_x = top_left.x().max(bottom_right.x());
+        let min_y = bottom_right.y().min(top_left.y());
+        let max_y = bottom_right.y().max(top_left.y());
+
+        let chunk_hash = |x: i64, y: i64, version: u64| {
+            let mut hasher = DefaultHasher::new();
+            (x, y, version).hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // summing keeps it independent of the order, so we can walk whichever is smaller
+        let side = |min: i64, max: i64| (max as i128 - min as i128 + 1) as u128;
+        let region_size = side(min_x, max_x).saturating_mul(side(min_y, max_y));
+        let changed = if region_size <= self.versions.len() as u128 {
+            let mut changed = 0u64;
+            for y in min_y..=max_y {
+                for x in min_x..=max_x {
+                    if let Ok(coordinates) = self.board.coordinates(x, y)
+                        && let Some(version) = self.versions.get(&coordinates)
+                    {
+                        changed = changed.wrapping_add(chunk_hash(x, y, *version));
+                    }
+                }
+            }
+            changed
+        } else {
+            self.versions
+                .iter()
+                .filter(|entry| {
+                    let (x, y) = (entry.key().x(), entry.key().y());
+                    (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y)
+                })
+                .fold(0u64, |changed, entry| {
+                    changed.wrapping_add(chunk_hash(
+                        entry.key().x(),
+                        entry.key().y(),
+                        *entry.value(),
+                    ))
+                })
+        };
+
+        let mut hasher = DefaultHasher::new();
+        (self.epoch, changed).hash(&mut hasher);
+        hasher.finish()
+    }
 }
+
 #[derive(Debug, Clone)]
 pub struct BoardManagerCommunicator {
     board_manager_tx: tokio::sync::mpsc::Sender<BoardManagerMessage>,
+    /// read only, the BoardManager bumps the versions
+    chunk_versions: Arc<ChunkVersions>,
 }
 
 impl BoardManagerCommunicator {
@@ -85,8 +247,134 @@ impl BoardManagerCommunicator {
             .await
             .unwrap();
 
-        let matrix_chunks = receiver.await.unwrap();
-        matrix_chunks
+        receiver.await.unwrap()
+    }
+
+    /// Whether the BoardManager loop is alive and answering messages
+    pub async fn ping(&self) -> bool {
+        let (sender, receiver) = oneshot::channel();
+
+        let ping = async {
+            self.board_manager_tx
+                .send(BoardManagerMessage::Ping(sender))
+                .await
+                .ok()?;
+            receiver.await.ok()
+        };
+
+        matches!(
+            tokio::time::timeout(HEALTH_CHECK_TIMEOUT, ping).await,
+            Ok(Some(()))
+        )
+    }
+
+    pub async fn get_stats(&self) -> Result<BoardStats, BoardManagerError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.board_manager_tx
+            .send(BoardManagerMessage::GetStats(sender))
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?;
+
+        receiver.await.map_err(|_| BoardManagerError::Unresponsive)
+    }
+
+    /// Delete the stored chunk, live clients of it receive the blank chunk
+    pub async fn reset_chunk(
+        &self,
+        coordinates: ChunkCoordinates,
+    ) -> Result<(), BoardManagerError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.board_manager_tx
+            .send(BoardManagerMessage::ResetChunk(coordinates, sender))
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?;
+
+        receiver
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?
+    }
+
+    /// Grow the bounds of the board, they are stored so they stay after a restart
+    pub async fn grow_bounds(&self, bounds: Bounds) -> Result<(), BoardManagerError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.board_manager_tx
+            .send(BoardManagerMessage::GrowBounds(bounds, sender))
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?;
+
+        receiver
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?
+    }
+
+    pub async fn get_tile(&self, tile: TileCoordinates) -> Result<Bytes, BoardManagerError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.board_manager_tx
+            .send(BoardManagerMessage::GetTile(tile, sender))
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?;
+
+        receiver.await.map_err(|_| BoardManagerError::Unresponsive)
+    }
+
+    /// Changes whenever a chunk in the region changes, see [`ChunkVersions::region_version`]
+    pub fn region_version(
+        &self,
+        top_left: ChunkCoordinates,
+        bottom_right: ChunkCoordinates,
+    ) -> u64 {
+        self.chunk_versions.region_version(top_left, bottom_right)
+    }
+
+    /// When the chunk last changed, see [`ChunkVersions::last_change`]
+    pub fn last_change(&self, coordinates: ChunkCoordinates) -> Option<SystemTime> {
+        self.chunk_versions.last_change(coordinates)
+    }
+
+    /// Sees a new value once a change of any chunk is counted, for tests to wait on
+    #[cfg(test)]
+    pub fn chunk_changes(&self) -> watch::Receiver<u64> {
+        self.chunk_versions.changes.subscribe()
+    }
+
+    /// Size, last modification and format of the stored chunk, `None` if it isn't stored
+    pub async fn chunk_metadata(
+        &self,
+        coordinates: ChunkCoordinates,
+    ) -> Result<Option<ChunkMetadata>, BoardManagerError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.board_manager_tx
+            .send(BoardManagerMessage::GetChunkMetadata(coordinates, sender))
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?;
+
+        receiver
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?
+    }
+
+    /// Ask the BoardManager to do a cheap operation on its storage
+    pub async fn probe_storage(&self) -> Result<(), BoardManagerError> {
+        let (sender, receiver) = oneshot::channel();
+
+        let probe = async {
+            self.board_manager_tx
+                .send(BoardManagerMessage::ProbeStorage(sender))
+                .await
+                .map_err(|_| BoardManagerError::Unresponsive)?;
+            receiver
+                .await
+                .map_err(|_| BoardManagerError::Unresponsive)?
+        };
+
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, probe)
+            .await
+            .map_err(|_| BoardManagerError::Unresponsive)?
     }
 }
 
@@ -96,7 +384,7 @@ where
     T: ChunkLoaderSaver + 'static,
 {
     /// the chunks currently managed my the BoardManager
-    chunks: dashmap::DashMap<ChunkCoordinates, HandlerData>,
+    chunks: Arc<dashmap::DashMap<ChunkCoordinates, HandlerData>>,
 
     /// The manager for updating the chunks, this is given to each chunk manager
     chunks_loader_saver: Arc<T>,
@@ -111,23 +399,48 @@ where
 
     /// The board manager receives messages from BoardManagerCommunicator
     board_manager_rx: mpsc::Receiver<BoardManagerMessage>,
+
+    /// Pixel updates of the whole board, counted by the ChunkManagers
+    board_pixels: Arc<Mutex<PixelCounter>>,
+
+    /// Rendered map tiles, invalidated by [`ChunkUpdate::Changed`]
+    tile_cache: Arc<TileCache>,
+    /// Bumped by [`ChunkUpdate::Changed`]
+    chunk_versions: Arc<ChunkVersions>,
+    /// chunks with a [`BoardManagerMessage::ResetChunk`] in progress, they don't get a new ChunkManager
+    resetting: Arc<dashmap::DashSet<ChunkCoordinates>>,
+    /// held while storing new bounds, so the stored ones are the ones in use
+    growing: Arc<tokio::sync::Mutex<()>>,
+
+    config: BoardConfig,
+    /// given to each ChunkManager
+    chunk_config: ChunkConfig,
 }
 
 impl<T> BoardManager<T>
 where
     T: ChunkLoaderSaver + 'static,
 {
-    pub fn start(chunks_loader_saver: T) -> BoardManagerCommunicator {
-        let (board_manager_tx, board_manager_rx) = mpsc::channel(100);
-        let (chunk_updates_tx, chunk_updates_rx) = mpsc::channel(100);
+    pub fn start(chunks_loader_saver: T, config: &Config) -> BoardManagerCommunicator {
+        let (board_manager_tx, board_manager_rx) = mpsc::channel(config.board.channel_size);
+        let (chunk_updates_tx, chunk_updates_rx) = mpsc::channel(config.board.channel_size);
+        let board = chunks_loader_saver.board().clone();
+        let chunk_versions = Arc::new(ChunkVersions::new(board.clone()));
 
         let board_manager = Self {
-            chunks: dashmap::DashMap::new(),
+            chunks: Arc::new(dashmap::DashMap::new()),
             chunks_loaded: 0.into(),
             chunks_loader_saver: Arc::new(chunks_loader_saver),
             chunk_m_updates_rx: chunk_updates_rx,
             board_manager_rx,
             chunk_m_updates_tx: chunk_updates_tx,
+            board_pixels: Arc::new(Mutex::new(PixelCounter::new())),
+            tile_cache: Arc::new(TileCache::new(board.clone())),
+            chunk_versions: chunk_versions.clone(),
+            resetting: Arc::new(dashmap::DashSet::new()),
+            growing: Arc::new(tokio::sync::Mutex::new(())),
+            config: config.board,
+            chunk_config: config.chunk,
         };
 
         // start the board manager
@@ -136,11 +449,10 @@ where
         });
 
         // the communicator is how the Appstate talks to the BoardManager
-        let board_manager_communicator = BoardManagerCommunicator {
+        BoardManagerCommunicator {
             board_manager_tx: board_manager_tx.clone(),
-        };
-
-        board_manager_communicator
+            chunk_versions,
+        }
     }
 
     async fn run(mut self) {
@@ -170,14 +482,113 @@ where
                         }
                         Some(BoardManagerMessage::GetChunk(coordinates, request_type, sender)) => {
                             debug!("BM - GetChunk request {:?}:{:?}", coordinates, request_type);
-                            let chunk = Self::read_chunk(&self.chunks,&self.chunks_loader_saver, coordinates, request_type).await;
-                            let _ = sender.send(chunk);
+                            let chunks_map = self.chunks.clone();
+                            let chunks_loader_saver = self.chunks_loader_saver.clone();
+
+                            tokio::spawn(async move {
+                                let chunk = Self::read_chunk(&chunks_map, &chunks_loader_saver, coordinates, request_type).await;
+                                let _ = sender.send(chunk);
+                            });
                         }
                         Some(BoardManagerMessage::GetHandler(coordinates, sender)) => {
                             debug!("BM - GetHandler request {:?}", coordinates);
                             let handler = self.get_chunk_handler(coordinates);
                             let _ = sender.send(handler);
                         }
+                        Some(BoardManagerMessage::Ping(sender)) => {
+                            let _ = sender.send(());
+                        }
+                        Some(BoardManagerMessage::ProbeStorage(sender)) => {
+                            debug!("BM - ProbeStorage request");
+                            let chunks_loader_saver = self.chunks_loader_saver.clone();
+
+                            // don't block the loop on a slow storage
+                            tokio::spawn(async move {
+                                let result = chunks_loader_saver
+                                    .probe()
+                                    .await
+                                    .map_err(|err| BoardManagerError::StorageUnreachable(format!("{:?}", err)));
+                                let _ = sender.send(result);
+                            });
+                        }
+                        Some(BoardManagerMessage::GetChunkMetadata(coordinates, sender)) => {
+                            debug!("BM - GetChunkMetadata request {:?}", coordinates);
+                            let chunks_loader_saver = self.chunks_loader_saver.clone();
+
+                            tokio::spawn(async move {
+                                let result = chunks_loader_saver
+                                    .metadata(coordinates)
+                                    .await
+                                    .map_err(|err| BoardManagerError::Storage(format!("{:?}", err)));
+                                let _ = sender.send(result);
+                            });
+                        }
+                        Some(BoardManagerMessage::GetStats(sender)) => {
+                            debug!("BM - GetStats request");
+                            // only the stats senders, cloning the HandlerData would count as a connection
+                            let stats_requesters = self
+                                .chunks
+                                .iter()
+                                .map(|entry| entry.value().stats_requester_tx.clone())
+                                .collect();
+                            let board_pixels = self.board_pixels.clone();
+                            let bounds = self.chunks_loader_saver.board().bounds();
+
+                            tokio::spawn(async move {
+                                let stats = Self::collect_stats(stats_requesters, &board_pixels, bounds).await;
+                                let _ = sender.send(stats);
+                            });
+                        }
+                        Some(BoardManagerMessage::GetTile(tile, sender)) => {
+                            debug!("BM - GetTile request {:?}", tile);
+                            let chunks_map = self.chunks.clone();
+                            let chunks_loader_saver = self.chunks_loader_saver.clone();
+                            let tile_cache = self.tile_cache.clone();
+
+                            tokio::spawn(async move {
+                                let png = Self::tile_png(&chunks_map, &chunks_loader_saver, &tile_cache, tile).await;
+                                let _ = sender.send(png);
+                            });
+                        }
+                        Some(BoardManagerMessage::ResetChunk(coordinates, sender)) => {
+                            info!("BM - ResetChunk request {:?}", coordinates);
+                            if !self.resetting.insert(coordinates) {
+                                let _ = sender.send(Err(BoardManagerError::Resetting));
+                                continue;
+                            }
+
+                            let chunks_map = self.chunks.clone();
+                            let chunks_loader_saver = self.chunks_loader_saver.clone();
+                            let chunk_versions = self.chunk_versions.clone();
+                            let tile_cache = self.tile_cache.clone();
+                            let resetting = self.resetting.clone();
+
+                            // the ChunkManager might be waiting on the loop to take its updates
+                            tokio::spawn(async move {
+                                let result = Self::reset_chunk(
+                                    &chunks_map,
+                                    &chunks_loader_saver,
+                                    &chunk_versions,
+                                    &tile_cache,
+                                    coordinates,
+                                )
+                                .await;
+                                resetting.remove(&coordinates);
+
+                                let _ = sender.send(result.map_err(|err| BoardManagerError::Storage(format!("{:?}", err))));
+                            });
+                        }
+                        Some(BoardManagerMessage::GrowBounds(bounds, sender)) => {
+                            info!("BM - GrowBounds request {:?}", bounds);
+                            let chunks_loader_saver = self.chunks_loader_saver.clone();
+                            let growing = self.growing.clone();
+
+                            tokio::spawn(async move {
+                                let _growing = growing.lock().await;
+                                let result = Self::grow_bounds(&chunks_loader_saver, bounds).await;
+                                let _ = sender.send(result);
+                            });
+                        }
                         None => {
                             panic!("Board manager is closed")
                         }
@@ -192,6 +603,10 @@ where
                                 // ! it needs to save itself before sending this message
                                 let _ = self.chunks.remove(&coords);
                             }
+                            ChunkUpdate::Changed(coords) => {
+                                self.chunk_versions.bump(coords);
+                                self.tile_cache.invalidate(coords);
+                            }
                             ChunkUpdate::_Save => {
                                 // ? Is probably better if Chunks just save themselves instead of the BoardManager
                             }
@@ -220,36 +635,100 @@ where
                 .ok(),
 
             ChunkRequest::Live => {
-                let handler = chunks.get(&coordinates);
+                // only the requester, the map stays locked while holding the entry,
+                // and a cloned HandlerData would count as a connection
+                let chunk_requester = chunks
+                    .get(&coordinates)
+                    .map(|entry| entry.value().chunk_requester_tx.clone());
+
+                if let Some(chunk_requester) = chunk_requester {
+                    let (sender, receiver) = oneshot::channel();
+                    if chunk_requester.send(sender).await.is_ok()
+                        && let Ok(chunk) = receiver.await
+                    {
+                        return Some(chunk);
+                    }
+                    // it stopped in the meantime, after saving the chunk
+                }
 
-                if let Some(handler) = handler {
-                    return Some(handler.fetch_chunk().await);
-                } else {
-                    // get from storage
-                    let chunk = chunks_loader_saver
-                        .load_chunk(coordinates, true)
-                        .await
-                        .map_err(|err| {
-                            error!("loading error setting default: {:?}", err);
-                        })
-                        .ok()?;
+                // get from storage
+                let chunk = chunks_loader_saver
+                    .load_chunk(coordinates, true)
+                    .await
+                    .map_err(|err| {
+                        error!("loading error setting default: {:?}", err);
+                    })
+                    .ok()?;
 
-                    return Some(chunk);
-                }
+                Some(chunk)
             }
         }
     }
 
+    /// Reset through the live ChunkManager, or delete the stored chunk when there is none
+    async fn reset_chunk(
+        chunks: &dashmap::DashMap<ChunkCoordinates, HandlerData>,
+        chunks_loader_saver: &T,
+        chunk_versions: &ChunkVersions,
+        tile_cache: &TileCache,
+        coordinates: ChunkCoordinates,
+    ) -> Result<(), paintplayground::chunk_db::ChunkLoaderSaverError> {
+        let reset_requester = chunks
+            .get(&coordinates)
+            .map(|entry| entry.value().reset_requester_tx.clone());
+
+        // the ChunkManager deletes it, or it would save it again
+        if let Some(reset_requester) = reset_requester
+            && let Some(result) = Self::reset_live_chunk(reset_requester).await
+        {
+            return result;
+        }
+
+        // not live (anymore), no new ChunkManager loads it while it is in `resetting`
+        chunks_loader_saver.delete_chunk(coordinates).await?;
+        chunk_versions.bump(coordinates);
+        tile_cache.invalidate(coordinates);
+        Ok(())
+    }
+
+    /// Store the bounds before using them, bounds which aren't stored would be lost on a restart
+    async fn grow_bounds(chunks_loader_saver: &T, bounds: Bounds) -> Result<(), BoardManagerError> {
+        let board = chunks_loader_saver.board();
+        board.can_grow(bounds)?;
+
+        chunks_loader_saver
+            .save_bounds(bounds)
+            .await
+            .map_err(|err| BoardManagerError::Storage(format!("{:?}", err)))?;
+        board.grow(bounds)?;
+        Ok(())
+    }
+
+    /// Ask a live ChunkManager to reset, `None` if it stopped before it could
+    async fn reset_live_chunk(
+        reset_requester: mpsc::Sender<
+            oneshot::Sender<Result<(), paintplayground::chunk_db::ChunkLoaderSaverError>>,
+        >,
+    ) -> Option<Result<(), paintplayground::chunk_db::ChunkLoaderSaverError>> {
+        let (sender, receiver) = oneshot::channel();
+        reset_requester.send(sender).await.ok()?;
+        receiver.await.ok()
+    }
+
     // get the data neccesary for a handler to start
     pub fn get_chunk_handler(
         &self,
         coordinates: ChunkCoordinates,
     ) -> Result<HandlerData, BoardManagerError> {
+        if self.resetting.contains(&coordinates) && !self.chunks.contains_key(&coordinates) {
+            return Err(BoardManagerError::Resetting);
+        }
+
         let handler = self
             .chunks
             .entry(coordinates)
             .or_try_insert_with(|| {
-                if self.chunks_loaded() < 100 {
+                if self.chunks_loaded() < self.config.max_live_chunks as u64 {
                     debug!("Creating new ChunkManager");
 
                     self.chunks_loaded
@@ -259,6 +738,8 @@ where
                         coordinates,
                         self.chunks_loader_saver.clone(),
                         self.chunk_m_updates_tx.clone(),
+                        self.board_pixels.clone(),
+                        self.chunk_config,
                     ))
                 } else {
                     debug!("Too many chunks loaded");
@@ -271,6 +752,130 @@ where
         Ok(handler)
     }
 
+    async fn tile_png(
+        chunks: &dashmap::DashMap<ChunkCoordinates, HandlerData>,
+        chunks_loader_saver: &T,
+        tile_cache: &TileCache,
+        tile: TileCoordinates,
+    ) -> Bytes {
+        if let Some(png) = tile_cache.png(&tile) {
+            return png;
+        }
+
+        let generation = tile_cache.generation(&tile);
+        let budget = TileBudget::default();
+        let (mosaic, complete) =
+            Self::render_tile(chunks, chunks_loader_saver, tile_cache, &budget, tile).await;
+        let board = chunks_loader_saver.board();
+        let png =
+            Bytes::from(Screenshot::from_chunks(vec![vec![Some(mosaic)]], board).create_png(1));
+
+        if complete {
+            tile_cache.insert_png(tile, png.clone(), generation);
+        }
+        png
+    }
+
+    /// The tile as a chunk: zoom 0 is the chunk itself,
+    /// higher zoom levels are the 4 tiles below downsampled into one.
+    ///
+    /// Not complete when the budget ran out before every chunk of it was loaded.
+    fn render_tile<'a>(
+        chunks: &'a dashmap::DashMap<ChunkCoordinates, HandlerData>,
+        chunks_loader_saver: &'a T,
+        tile_cache: &'a TileCache,
+        budget: &'a TileBudget,
+        tile: TileCoordinates,
+    ) -> BoxFuture<'a, (Chunk, bool)> {
+        Box::pin(async move {
+            let board = chunks_loader_saver.board();
+            // outside of the board is empty
+            if !tile.intersects(&board.bounds()) {
+                return (board.blank_chunk(), true);
+            }
+
+            let Some(children) = tile.children() else {
+                let Ok(coordinates) = board.coordinates(tile.x(), tile.y()) else {
+                    return (board.blank_chunk(), true);
+                };
+                if !budget.take() {
+                    return (board.blank_chunk(), false);
+                }
+
+                let _permit = tile_cache.loads.acquire().await;
+                let chunk =
+                    Self::read_chunk(chunks, chunks_loader_saver, coordinates, ChunkRequest::Live)
+                        .await
+                        .unwrap_or_else(|| board.blank_chunk());
+                return (chunk, true);
+            };
+
+            if let Some(mosaic) = tile_cache.mosaic(&tile) {
+                return (mosaic, true);
+            }
+
+            let generation = tile_cache.generation(&tile);
+            let rendered = futures::future::join_all(children.into_iter().map(|child| {
+                Self::render_tile(chunks, chunks_loader_saver, tile_cache, budget, child)
+            }))
+            .await;
+
+            let mut mosaic = board.blank_chunk();
+            for (quadrant, (child, _)) in rendered.iter().enumerate() {
+                tiles::downsample_into(&mut mosaic, child, quadrant % 2, quadrant / 2);
+            }
+
+            let complete = rendered.iter().all(|(_, complete)| *complete);
+            if complete {
+                tile_cache.insert_mosaic(tile, mosaic.clone(), generation);
+            }
+            (mosaic, complete)
+        })
+    }
+
+    async fn collect_stats(
+        stats_requesters: Vec<mpsc::Sender<oneshot::Sender<ChunkStats>>>,
+        board_pixels: &Mutex<PixelCounter>,
+        bounds: Bounds,
+    ) -> BoardStats {
+        // a ChunkManager that is busy or shutting down is left out
+        let mut chunk_stats: Vec<ChunkStats> =
+            futures::future::join_all(stats_requesters.into_iter().map(|requester| async move {
+                let (stats_tx, stats_rx) = oneshot::channel();
+                requester.send(stats_tx).await.ok()?;
+                tokio::time::timeout(CHUNK_STATS_TIMEOUT, stats_rx)
+                    .await
+                    .ok()?
+                    .ok()
+            }))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        chunk_stats.sort_by_key(|stats| (stats.x, stats.y));
+
+        let mut most_active: Vec<ChunkStats> = chunk_stats
+            .iter()
+            .filter(|stats| stats.pixels_last_minute > 0)
+            .cloned()
+            .collect();
+        most_active.sort_by_key(|stats| std::cmp::Reverse(stats.pixels_last_minute));
+        most_active.truncate(MOST_ACTIVE_CHUNKS);
+
+        let throughput = board_pixels
+            .lock()
+            .map(|counter| counter.throughput())
+            .unwrap_or_else(|poisoned| poisoned.into_inner().throughput());
+
+        BoardStats {
+            bounds,
+            live_chunks: chunk_stats.len(),
+            throughput,
+            chunks: chunk_stats,
+            most_active,
+        }
+    }
+
     fn chunks_loaded(&self) -> u64 {
         self.chunks_loaded.load(std::sync::atomic::Ordering::SeqCst)
     }
@@ -296,7 +901,7 @@ where
 
         for y in (min_y..=max_y).rev() {
             for x in min_x..=max_x {
-                if let Ok(coordinate) = ChunkCoordinates::new(x, y) {
+                if let Ok(coordinate) = chunks_loader_saver.board().coordinates(x, y) {
                     coordinates.push(coordinate);
                 }
             }
@@ -312,7 +917,7 @@ where
         for y in (min_y..=max_y).rev() {
             let mut row = Vec::with_capacity(width);
             for x in min_x..=max_x {
-                if let Ok(coordinate) = ChunkCoordinates::new(x, y) {
+                if let Ok(coordinate) = chunks_loader_saver.board().coordinates(x, y) {
                     // Find this coordinate in our fetched results
                     let position = coordinates.iter().position(|&c| c == coordinate);
                     if let Some(pos) = position {
@@ -1,14 +1,43 @@
-use std::{error::Error, time::Duration};
-
+use std::{
+    error::Error,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::sync::watch;
 use tracing::error;
 
-use paintplayground::{chunk_db::ChunkLoaderSaver, types::*};
+use crate::config::ChunkConfig;
+use crate::stats::PixelCounter;
+use paintplayground::{
+    chunk_db::{ChunkLoaderSaver, ChunkLoaderSaverError},
+    types::*,
+};
+
+/// How often a ChunkManager tries to load its chunk before it starts read only
+const LOAD_ATTEMPTS: u32 = 3;
+/// Wait before the first retry of a failed load, doubled for every retry after it
+const FIRST_LOAD_RETRY: Duration = Duration::from_millis(200);
+/// Longest wait between the retries of a read only chunk
+const MAX_LOAD_RETRY: Duration = Duration::from_secs(60);
+
+/// What a ChunkManager broadcasts to the websockets of its chunk
+#[derive(Debug, Clone)]
+pub enum ChunkBroadcast {
+    /// the buffered pixel updates
+    Updates(Vec<PackedCell>),
+    /// the chunk was replaced, clients have to redraw all of it
+    EntireChunk(Chunk),
+}
 
 pub enum ChunkUpdate {
     /// The chunk manager doesn't have any clients, and can be removed
     Clear(ChunkCoordinates),
     /// Enough updates have been made to the chunk, and it should be saved
     _Save,
+    /// Pixels of the chunk changed, anything derived from it is outdated
+    Changed(ChunkCoordinates),
 }
 
 #[derive(Debug)]
@@ -25,7 +54,7 @@ where
     chunk_saver: Arc<T>,
 
     /// broadcast updates to all websockets connections
-    broadcaster_tx: broadcast::Sender<Vec<PackedCell>>,
+    broadcaster_tx: broadcast::Sender<ChunkBroadcast>,
     /// receive updates from the websockets
     update_rx: mpsc::Receiver<Vec<PackedCell>>,
 
@@ -33,12 +62,45 @@ where
     chunk_requester_rx: mpsc::Receiver<oneshot::Sender<Chunk>>,
     /// Pinging to know if the ChunkManager is still alive
     ping_chunk_requester_rx: mpsc::Receiver<oneshot::Sender<()>>,
+    /// Requests for the stats of this chunk
+    stats_requester_rx: mpsc::Receiver<oneshot::Sender<ChunkStats>>,
+    /// Requests to delete the stored chunk and start over blank
+    reset_requester_rx: mpsc::Receiver<oneshot::Sender<Result<(), ChunkLoaderSaverError>>>,
 
     /// Send a message to the BoardManager, to tell you are ded.
     chunk_m_updates_tx: mpsc::Sender<ChunkUpdate>,
 
     /// Keep track when was the last change, or if no changes: when it started
     last_change: std::time::Instant,
+
+    /// pixel updates received by this chunk
+    pixels: PixelCounter,
+    /// pixel updates received by the whole board, shared between all ChunkManagers
+    board_pixels: Arc<Mutex<PixelCounter>>,
+
+    config: ChunkConfig,
+
+    /// Loading the stored chunk failed, so it's shown blank and never saved.
+    /// Saving would overwrite the stored data, which might still be recoverable.
+    /// The websockets are told when it changes
+    read_only_tx: watch::Sender<bool>,
+    /// When to try loading a read only chunk again, `None` if the stored bytes are broken
+    next_load_attempt: Option<Instant>,
+    /// doubled after every failed retry, up to [`MAX_LOAD_RETRY`]
+    load_retry_delay: Duration,
+}
+
+/// Stats of a live [`ChunkManager`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkStats {
+    pub x: i64,
+    pub y: i64,
+    pub connections: usize,
+    pub seconds_since_last_change: u64,
+    /// pixel updates in the last minute
+    pub pixels_last_minute: u64,
+    /// pixel updates since the ChunkManager was started
+    pub pixels_total: u64,
 }
 
 impl<T> ChunkManager<T>
@@ -52,29 +114,67 @@ where
         coordinates: ChunkCoordinates,
         chunk_saver: Arc<T>,
         chunk_m_updates_tx: mpsc::Sender<ChunkUpdate>,
+        board_pixels: Arc<Mutex<PixelCounter>>,
+        config: ChunkConfig,
     ) -> HandlerData {
-        let (update_tx, update_rx) = mpsc::channel(1000);
-        let (broadcaster_tx, broadcast_rx) = broadcast::channel(1000);
+        let (update_tx, update_rx) = mpsc::channel(config.update_channel_size);
+        let (broadcaster_tx, broadcast_rx) = broadcast::channel(config.update_channel_size);
 
-        let (chunk_requester_tx, chunk_requester_rx) = mpsc::channel(100);
-        let (ping_chunk_requester_tx, ping_chunk_requester_rx) = mpsc::channel(100);
+        let (chunk_requester_tx, chunk_requester_rx) = mpsc::channel(config.request_channel_size);
+        let (ping_chunk_requester_tx, ping_chunk_requester_rx) =
+            mpsc::channel(config.request_channel_size);
+        let (stats_requester_tx, stats_requester_rx) = mpsc::channel(config.request_channel_size);
+        let (reset_requester_tx, reset_requester_rx) = mpsc::channel(config.request_channel_size);
+        let (read_only_tx, read_only_rx) = watch::channel(false);
 
         let handler_data = HandlerData {
             broadcast_rx,
             update_tx,
             chunk_requester_tx,
             ping_chunk_requester_tx,
+            stats_requester_tx,
+            reset_requester_tx,
+            read_only_rx,
         };
 
         debug!("Starting chunk manager for {:?}", coordinates);
         tokio::spawn(async move {
-            let chunk = chunk_saver
-                .load_chunk(coordinates, true)
-                .await
-                .map_err(|err| {
-                    error!("loading error setting default: {:?}", err);
-                })
-                .unwrap_or_default();
+            let mut load_retry_delay = FIRST_LOAD_RETRY;
+            let mut attempt = 1;
+            let (chunk, next_load_attempt) = loop {
+                match chunk_saver.load_chunk(coordinates, true).await {
+                    Ok(chunk) => break (chunk, None),
+                    // loading the same bytes again won't help
+                    Err(err @ ChunkLoaderSaverError::CompressionError(_)) => {
+                        error!(
+                            "CM - {:?} is broken, it stays blank and read only until reset: {:?}",
+                            coordinates, err
+                        );
+                        read_only_tx.send_replace(true);
+                        break (chunk_saver.board().blank_chunk(), None);
+                    }
+                    Err(err) if attempt < LOAD_ATTEMPTS => {
+                        warn!(
+                            "CM - {:?} failed to load, retrying in {:?}: {:?}",
+                            coordinates, load_retry_delay, err
+                        );
+                        tokio::time::sleep(load_retry_delay).await;
+                        load_retry_delay = (load_retry_delay * 2).min(MAX_LOAD_RETRY);
+                        attempt += 1;
+                    }
+                    Err(err) => {
+                        error!(
+                            "CM - {:?} failed to load {} times, it stays blank and read only until it loads: {:?}",
+                            coordinates, attempt, err
+                        );
+                        read_only_tx.send_replace(true);
+                        break (
+                            chunk_saver.board().blank_chunk(),
+                            Some(Instant::now() + load_retry_delay),
+                        );
+                    }
+                }
+            };
 
             let chunk_manager = Self {
                 chunk_saver,
@@ -84,8 +184,16 @@ where
                 update_rx,
                 chunk_requester_rx,
                 ping_chunk_requester_rx,
+                stats_requester_rx,
+                reset_requester_rx,
                 chunk_m_updates_tx,
                 last_change: std::time::Instant::now(),
+                pixels: PixelCounter::new(),
+                board_pixels,
+                config,
+                read_only_tx,
+                next_load_attempt,
+                load_retry_delay,
             };
 
             chunk_manager.run().await;
@@ -101,15 +209,20 @@ where
             let mut smaller_buffer = Vec::new();
             changed = false;
 
-            let timeout = tokio::time::sleep(Duration::from_millis(*crate::CLEAR_BUFFER_INTERVAL));
+            let timeout = tokio::time::sleep(self.config.clear_buffer_interval());
             tokio::pin!(timeout);
 
             loop {
                 tokio::select! {
                     // handle updates from the websockets
                     Some(changes) = self.update_rx.recv() => {
+                        if self.read_only() {
+                            debug!("CH - {:?} is read only, dropping an update", self.coordinates);
+                            continue;
+                        }
                         self.last_change = std::time::Instant::now();
                         debug!("CH - {:?} got an update", self.coordinates);
+                        self.count_pixels(changes.len() as u64);
                         // todo, for contested chunks, use chunk as buffer
                         smaller_buffer.extend(changes);
                         changed = true;
@@ -124,6 +237,19 @@ where
                         debug!("CM - {:?} got a ping request, responding...", self.coordinates);
                         ping.send(()).unwrap();
                     }
+                    // handle stats requests
+                    Some(request) = self.stats_requester_rx.recv() => {
+                        let _ = request.send(self.stats());
+                    }
+                    // handle resets, updates buffered before it are dropped with the old chunk
+                    Some(request) = self.reset_requester_rx.recv() => {
+                        let result = self.reset().await;
+                        if result.is_ok() {
+                            smaller_buffer.clear();
+                            changed = false;
+                        }
+                        let _ = request.send(result);
+                    }
                     _ = &mut timeout => {
                         // breaking so we need to empty the smaller_buffer
                         break;
@@ -131,11 +257,13 @@ where
                 }
             }
 
+            self.retry_load().await;
+
             if !changed {
                 // check if there are connections
                 if self.no_connections() {
-                    // check if there have been no changes past 5 minutes
-                    if self.last_change.elapsed().as_secs() > 60 * 5 {
+                    // check if there have been no changes for the idle timeout
+                    if self.last_change.elapsed() > self.config.idle_timeout() {
                         break;
                     }
                 }
@@ -167,14 +295,12 @@ where
             }
 
             // broadcast the changes made to all the clients
-            self.broadcast(last_changes);
+            self.broadcast(ChunkBroadcast::Updates(last_changes));
+            self.notify_changed();
 
             // todo, only save every some time. and save on exit
             // save the chunk, if it has been changed
-            let _ = self
-                .chunk_saver
-                .save_chunk(self.chunk.clone(), self.coordinates)
-                .await;
+            self.save().await;
         }
 
         // loop is stopped, lets destroy ourselves
@@ -191,13 +317,112 @@ where
         }
     }
 
-    fn broadcast(&mut self, messages: Vec<PackedCell>) {
-        debug!(
-            "CM - {:?} is broadcasting {}",
-            self.coordinates,
-            messages.len()
-        );
-        self.broadcaster_tx.send(messages).unwrap();
+    /// Delete the stored chunk and continue with a blank one, sending it to every client
+    async fn reset(&mut self) -> Result<(), ChunkLoaderSaverError> {
+        self.chunk_saver.delete_chunk(self.coordinates).await?;
+        info!("CM - {:?} is reset", self.coordinates);
+
+        self.chunk = self.chunk_saver.board().blank_chunk();
+        // nothing left to overwrite
+        self.read_only_tx.send_replace(false);
+        self.next_load_attempt = None;
+        self.last_change = std::time::Instant::now();
+
+        self.broadcast(ChunkBroadcast::EntireChunk(self.chunk.clone()));
+        self.notify_changed();
+
+        Ok(())
+    }
+
+    fn read_only(&self) -> bool {
+        *self.read_only_tx.borrow()
+    }
+
+    /// Load a read only chunk again once its retry is due, the clients get it when it loads
+    async fn retry_load(&mut self) {
+        let Some(next_load_attempt) = self.next_load_attempt else {
+            return;
+        };
+        if Instant::now() < next_load_attempt {
+            return;
+        }
+
+        match self.chunk_saver.load_chunk(self.coordinates, true).await {
+            Ok(chunk) => {
+                info!(
+                    "CM - {:?} loaded, it isn't read only anymore",
+                    self.coordinates
+                );
+                self.chunk = chunk;
+                self.next_load_attempt = None;
+                self.read_only_tx.send_replace(false);
+
+                self.broadcast(ChunkBroadcast::EntireChunk(self.chunk.clone()));
+                self.notify_changed();
+            }
+            Err(err) => {
+                self.load_retry_delay = (self.load_retry_delay * 2).min(MAX_LOAD_RETRY);
+                self.next_load_attempt = match err {
+                    ChunkLoaderSaverError::CompressionError(_) => None,
+                    _ => Some(Instant::now() + self.load_retry_delay),
+                };
+                warn!(
+                    "CM - {:?} still fails to load, next try in {:?}: {:?}",
+                    self.coordinates, self.load_retry_delay, err
+                );
+            }
+        }
+    }
+
+    /// Save the chunk, unless it's [`read_only`](ChunkManager::read_only)
+    async fn save(&self) {
+        if self.read_only() {
+            return;
+        }
+
+        // silent error, let's go
+        let _ = self
+            .chunk_saver
+            .save_chunk(self.chunk.clone(), self.coordinates)
+            .await;
+    }
+
+    /// Tell the BoardManager the chunk changed, without waiting on it
+    fn notify_changed(&self) {
+        let update = ChunkUpdate::Changed(self.coordinates);
+
+        if let Err(mpsc::error::TrySendError::Full(update)) =
+            self.chunk_m_updates_tx.try_send(update)
+        {
+            let chunk_m_updates_tx = self.chunk_m_updates_tx.clone();
+            tokio::spawn(async move {
+                let _ = chunk_m_updates_tx.send(update).await;
+            });
+        }
+    }
+
+    fn count_pixels(&mut self, pixels: u64) {
+        self.pixels.record(pixels);
+        if let Ok(mut board_pixels) = self.board_pixels.lock() {
+            board_pixels.record(pixels);
+        }
+    }
+
+    fn stats(&self) -> ChunkStats {
+        ChunkStats {
+            x: self.coordinates.x(),
+            y: self.coordinates.y(),
+            // the BoardManager keeps a HandlerData of every live chunk as well
+            connections: self.connections_quantity().saturating_sub(1),
+            seconds_since_last_change: self.last_change.elapsed().as_secs(),
+            pixels_last_minute: self.pixels.in_last(60),
+            pixels_total: self.pixels.total(),
+        }
+    }
+
+    fn broadcast(&mut self, message: ChunkBroadcast) {
+        debug!("CM - {:?} is broadcasting", self.coordinates);
+        self.broadcaster_tx.send(message).unwrap();
     }
 
     async fn delete_yourself(&self) -> Result<(), Box<dyn Error>> {
@@ -207,20 +432,18 @@ where
             return Err("There are senders".into());
         }
 
+        // save the chunk, One LAST TIME
+        // before the BoardManager forgets us, so a reset after it can't be overwritten
+        self.save().await;
+
         // send request to BoardManager to remove yourself
         // if errors everything is ded
-        let _ = self
-            .chunk_m_updates_tx
+        self.chunk_m_updates_tx
             .send(ChunkUpdate::Clear(self.coordinates))
             .await?;
 
-        // save the chunk, One LAST TIME
-        let _ = self
-            .chunk_saver
-            .save_chunk(self.chunk.clone(), self.coordinates); // silent error, let's go
-
         // You can stop now
-        return Ok(());
+        Ok(())
     }
 
     fn connections_quantity(&self) -> usize {
@@ -236,11 +459,15 @@ where
 
 #[derive(Debug)]
 pub struct HandlerData {
-    pub broadcast_rx: broadcast::Receiver<Vec<PackedCell>>,
+    pub broadcast_rx: broadcast::Receiver<ChunkBroadcast>,
     pub update_tx: mpsc::Sender<Vec<PackedCell>>,
 
     pub chunk_requester_tx: mpsc::Sender<oneshot::Sender<Chunk>>,
     pub ping_chunk_requester_tx: mpsc::Sender<oneshot::Sender<()>>,
+    pub stats_requester_tx: mpsc::Sender<oneshot::Sender<ChunkStats>>,
+    pub reset_requester_tx: mpsc::Sender<oneshot::Sender<Result<(), ChunkLoaderSaverError>>>,
+    /// whether the chunk is read only, see [`ChunkManager::read_only`]
+    pub read_only_rx: watch::Receiver<bool>,
 }
 
 impl Clone for HandlerData {
@@ -251,6 +478,9 @@ impl Clone for HandlerData {
 
             chunk_requester_tx: self.chunk_requester_tx.clone(),
             ping_chunk_requester_tx: self.ping_chunk_requester_tx.clone(),
+            stats_requester_tx: self.stats_requester_tx.clone(),
+            reset_requester_tx: self.reset_requester_tx.clone(),
+            read_only_rx: self.read_only_rx.clone(),
         }
     }
 }
@@ -0,0 +1,268 @@
+use std::{net::IpAddr, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::Config;
+
+use paintplayground::{chunk_db::StorageBackend, types::*};
+
+/// Paint playground server and tools for its storage
+///
+/// Settings are read from the config file, then environment variables (or `.env`), then flags.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML config file, `config.toml` is used if it exists
+    #[arg(long, global = true, env = "CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Chunks from the center to the edge of the board, in every direction [env: CHUNKS_IN_DIRECTION]
+    #[arg(long, global = true, value_parser = clap::value_parser!(i64).range(0..))]
+    pub chunks_in_direction: Option<i64>,
+
+    /// Pixels in a row and rows in a chunk, has to match the stored chunks [env: CHUNK_LENGTH]
+    #[arg(long, global = true)]
+    pub chunk_length: Option<usize>,
+
+    /// Board the storage tools work on, by its name in the config. The server serves all of them
+    #[arg(long, global = true, default_value = MAIN_BOARD)]
+    pub board: String,
+
+    /// Without a subcommand the server is started with these
+    #[command(flatten)]
+    pub serve: ServeArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the websocket server (default)
+    Serve(ServeArgs),
+    /// Save a screenshot of a region, read straight from storage
+    Plot(PlotArgs),
+    /// Copy stored chunks from a backend into a directory
+    Export(ExportArgs),
+    /// Upload chunk files from a directory into a backend
+    Import(ImportArgs),
+    /// Check every stored object: names, bounds, format bytes, decompression and sizes
+    Verify(VerifyArgs),
+    /// Summary of the chunks stored in a region
+    Stats(StatsArgs),
+    /// Copy every chunk from one backend to another, optionally re-encoding it
+    Migrate(MigrateArgs),
+    /// Train a zstd dictionary on the stored chunks, saved into the dictionaries directory
+    TrainDictionary(TrainDictionaryArgs),
+}
+
+impl Command {
+    /// The backend a storage tool reads from, `None` for serve which reads every board
+    pub fn source_backend(&self) -> Option<StorageBackend> {
+        match self {
+            Self::Serve(_) => None,
+            Self::Plot(args) => Some(args.backend),
+            Self::Export(args) => Some(args.backend),
+            Self::Import(args) => Some(args.backend),
+            Self::Verify(args) => Some(args.backend),
+            Self::Stats(args) => Some(args.backend),
+            Self::Migrate(args) => Some(args.from),
+            Self::TrainDictionary(args) => Some(args.backend),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on [env: BIND_ADDRESS] [default: 0.0.0.0]
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+
+    /// Port to listen on [env: PORT] [default: 3001]
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Where the chunks are stored [env: STORAGE_BACKEND] [default: r2]
+    #[arg(long)]
+    pub backend: Option<StorageBackend>,
+
+    /// Milliseconds a ChunkManager buffers updates before applying and broadcasting them [env: CLEAR_BUFFER_INTERVAL] [default: 500]
+    #[arg(long)]
+    pub clear_buffer_interval: Option<u64>,
+}
+
+impl ServeArgs {
+    /// Flags win over the config file and env
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.server.bind = bind;
+        }
+        if let Some(port) = self.port {
+            config.server.port = port;
+        }
+        if let Some(backend) = self.backend {
+            config.server.backend = backend;
+        }
+        if let Some(interval) = self.clear_buffer_interval {
+            config.chunk.clear_buffer_interval_ms = interval;
+        }
+    }
+}
+
+/// A rectangle of chunks, defaults to the whole board
+#[derive(Debug, Args)]
+pub struct RegionArgs {
+    /// Left chunk
+    #[arg(long, allow_negative_numbers = true)]
+    pub x: Option<i64>,
+    /// Top chunk
+    #[arg(long, allow_negative_numbers = true)]
+    pub y: Option<i64>,
+    /// Right chunk
+    #[arg(long, allow_negative_numbers = true)]
+    pub x2: Option<i64>,
+    /// Bottom chunk
+    #[arg(long, allow_negative_numbers = true)]
+    pub y2: Option<i64>,
+}
+
+impl RegionArgs {
+    /// top left and bottom right of the region, by default the whole board
+    pub fn corners(&self, board: &Board) -> Result<(ChunkCoordinates, ChunkCoordinates), String> {
+        let bounds = board.bounds();
+        let defaulted = [self.x, self.y, self.x2, self.y2].contains(&None);
+        if bounds.is_infinite() && defaulted {
+            return Err(
+                "the board is infinite, give the region with --x, --y, --x2 and --y2".to_string(),
+            );
+        }
+
+        let x = self.x.unwrap_or(bounds.min_x);
+        let y = self.y.unwrap_or(bounds.max_y);
+        let x2 = self.x2.unwrap_or(bounds.max_x);
+        let y2 = self.y2.unwrap_or(bounds.min_y);
+
+        if x > x2 || y < y2 {
+            return Err(format!(
+                "({}, {}) has to be the top left of ({}, {})",
+                x, y, x2, y2
+            ));
+        }
+
+        let top_left = board.coordinates(x, y).map_err(|err| err.to_string())?;
+        let bottom_right = board.coordinates(x2, y2).map_err(|err| err.to_string())?;
+
+        Ok((top_left, bottom_right))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct PlotArgs {
+    #[command(flatten)]
+    pub region: RegionArgs,
+
+    /// Pixels per pixel of the board, 1 to 8 [default: the highest that fits in 512_000_000 pixels]
+    #[arg(long, short, value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub q: Option<u8>,
+
+    /// File to save to, the extension picks the image format
+    #[arg(long, short, default_value = "screenshot.png")]
+    pub output: String,
+
+    /// Where the chunks are stored
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "file")]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub region: RegionArgs,
+
+    /// Directory to write `{x}_{y}.chunk` files into
+    #[arg(long)]
+    pub to: String,
+
+    /// Where the chunks are stored
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "r2")]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// Directory with `{x}_{y}.chunk` files
+    #[arg(long)]
+    pub from: String,
+
+    /// Where the chunks are stored
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "r2")]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Move the broken objects into `quarantine/`, where the server won't load them
+    #[arg(long)]
+    pub quarantine: bool,
+
+    /// Where the chunks are stored
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "r2")]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub region: RegionArgs,
+
+    /// Where the chunks are stored
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "r2")]
+    pub backend: StorageBackend,
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateArgs {
+    /// Backend to read the chunks from
+    #[arg(long)]
+    pub from: StorageBackend,
+
+    /// Backend to write the chunks to, can be the same as `--from` to recompress in place
+    #[arg(long)]
+    pub to: StorageBackend,
+
+    /// Re-encode every chunk: none, zstd, zstd:{level}, zstd-dict:{id}, lz4, rle, rle-zstd,
+    /// or smallest:{codec},{codec} to keep the smallest per chunk. Without it the stored bytes are copied as they are
+    #[arg(long)]
+    pub compression: Option<CompressionPolicy>,
+
+    /// Chunks migrated at the same time
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+
+    /// Progress file, chunks listed in it are skipped and migrated chunks are added to it
+    #[arg(long)]
+    pub resume: Option<PathBuf>,
+
+    /// Only report how many chunks would be migrated and the size difference
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TrainDictionaryArgs {
+    /// Stored with every chunk compressed with it, can't be reused for another dictionary
+    #[arg(long)]
+    pub id: u8,
+
+    /// Maximum size of the dictionary in bytes
+    #[arg(long, default_value_t = 16 * 1024)]
+    pub size: usize,
+
+    /// Maximum chunks to train on
+    #[arg(long, default_value_t = 10_000)]
+    pub samples: usize,
+
+    /// Where the chunks are stored
+    #[arg(long, env = "STORAGE_BACKEND", default_value = "r2")]
+    pub backend: StorageBackend,
+}
@@ -0,0 +1,613 @@
+//! The storage tools of the cli, every command works on any [`ChunkLoaderSaver`]
+
+use std::path::Path;
+
+use futures::{StreamExt, TryStreamExt};
+
+use paintplayground::{
+    chunk_db::{
+        ChunkLoaderSaver, ChunkLoaderSaverError, LIST_PAGE_SIZE, QUARANTINE_DIR, list_objects,
+    },
+    compression::{self, Compression},
+    types::*,
+};
+
+/// How many chunks the commands load or save at the same time
+const COMMAND_CONCURRENCY: usize = 32;
+
+/// Run `$body` with `$saver` bound to the storage of the board in the [`StorageBackend`](paintplayground::chunk_db::StorageBackend)
+macro_rules! with_backend {
+    ($backend:expr, $board:expr, |$saver:ident| $body:expr) => {
+        match $backend {
+            paintplayground::chunk_db::StorageBackend::File => {
+                let $saver =
+                    paintplayground::chunk_db::SimpleToFileSaver::with_board($board.clone());
+                $body
+            }
+            paintplayground::chunk_db::StorageBackend::R2 => {
+                let $saver = paintplayground::chunk_db::ChunkLoaderSaver::for_board(
+                    &paintplayground::chunk_db::CFR2ChunkSaver::new_from_env(),
+                    $board.clone(),
+                );
+                $body
+            }
+        }
+    };
+}
+pub(crate) use with_backend;
+
+/// Every coordinate of the region on the board, top row first
+fn region_coordinates(
+    board: &Board,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+) -> impl Iterator<Item = ChunkCoordinates> {
+    (bottom_right.y()..=top_left.y()).rev().flat_map(move |y| {
+        (top_left.x()..=bottom_right.x()).filter_map(move |x| board.coordinates(x, y).ok())
+    })
+}
+
+/// Load the stored chunks of the region concurrently, missing chunks are skipped
+fn load_region<T: ChunkLoaderSaver>(
+    loader: &T,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+) -> impl futures::Stream<Item = (ChunkCoordinates, Result<Chunk, ChunkLoaderSaverError>)> {
+    futures::stream::iter(region_coordinates(loader.board(), top_left, bottom_right))
+        .map(move |coordinates| async move {
+            (coordinates, loader.load_chunk(coordinates, false).await)
+        })
+        .buffer_unordered(COMMAND_CONCURRENCY)
+        .filter(|(_, result)| {
+            std::future::ready(!matches!(result, Err(ChunkLoaderSaverError::ChunkNotFound)))
+        })
+}
+
+pub async fn export<T: ChunkLoaderSaver>(
+    loader: &T,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+    to: &Path,
+) -> Result<(), String> {
+    std::fs::create_dir_all(to).map_err(|err| format!("can't create {:?}: {}", to, err))?;
+
+    let mut exported = 0;
+    let mut failed = 0;
+
+    let mut chunks = std::pin::pin!(load_region(loader, top_left, bottom_right));
+    while let Some((coordinates, result)) = chunks.next().await {
+        let written = result
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|chunk| {
+                let board = loader.board();
+                chunk
+                    .to_storage_bytes(&board.compression, &board.dictionaries)
+                    .map_err(|err| err.to_string())
+            })
+            .and_then(|data| {
+                std::fs::write(to.join(coordinates.object_name()), data)
+                    .map_err(|err| err.to_string())
+            });
+
+        match written {
+            Ok(()) => exported += 1,
+            Err(err) => {
+                eprintln!("{:?}: {}", coordinates, err);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "exported {} chunks to {:?}, {} failed",
+        exported, to, failed
+    );
+    if failed > 0 {
+        return Err(format!("{} chunks failed to export", failed));
+    }
+    Ok(())
+}
+
+/// Coordinates on the board from a `{x}_{y}.chunk` file name
+fn coordinates_from_file_name(board: &Board, name: &str) -> Option<ChunkCoordinates> {
+    let (x, y) = ChunkCoordinates::parse_object_name(name)?;
+    board.coordinates(x, y).ok()
+}
+
+pub async fn import<T: ChunkLoaderSaver>(saver: &T, from: &Path) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(from).map_err(|err| format!("can't read {:?}: {}", from, err))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+
+        match coordinates_from_file_name(saver.board(), name) {
+            Some(coordinates) => files.push((coordinates, path)),
+            None => eprintln!("skipping {:?}, not a chunk on this board", path),
+        }
+    }
+
+    let results: Vec<Result<(), String>> = futures::stream::iter(files)
+        .map(|(coordinates, path)| async move {
+            let data = std::fs::read(&path).map_err(|err| format!("{:?}: {}", path, err))?;
+            let chunk = Chunk::decode_for(&data, saver.board())
+                .map_err(|err| format!("{:?}: {}", path, err))?;
+
+            saver
+                .save_chunk(chunk, coordinates)
+                .await
+                .map_err(|err| format!("{:?}: {:?}", coordinates, err))
+        })
+        .buffer_unordered(COMMAND_CONCURRENCY)
+        .collect()
+        .await;
+
+    let failed: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+    for err in &failed {
+        eprintln!("{}", err);
+    }
+
+    println!("imported {:?}, {} failed", from, failed.len());
+    if !failed.is_empty() {
+        return Err(format!("{} chunks failed to import", failed.len()));
+    }
+    Ok(())
+}
+
+/// Names of every stored object
+async fn stored_names<T: ChunkLoaderSaver>(storage: &T) -> Result<Vec<String>, String> {
+    list_objects(storage, LIST_PAGE_SIZE)
+        .map_ok(|object| object.name)
+        .try_collect()
+        .await
+        .map_err(|err| format!("can't list the stored chunks: {:?}", err))
+}
+
+/// What is wrong with a stored object, found by [`verify`]
+#[derive(Debug, thiserror::Error)]
+enum ObjectProblem {
+    #[error("not named like a chunk")]
+    BadName,
+    #[error(transparent)]
+    OutOfBounds(#[from] OutOfBoundsError),
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    /// the storage didn't return it, which might not be the object's fault
+    #[error("unreadable: {0}")]
+    Unreadable(String),
+}
+
+impl ObjectProblem {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::BadName => "bad name",
+            Self::OutOfBounds(_) => "out of bounds",
+            Self::Decode(DecodeError::Empty) => "empty",
+            Self::Decode(DecodeError::UnknownFormat(_)) => "unknown format byte",
+            Self::Decode(DecodeError::Decompression { .. }) => "failed decompression",
+            Self::Decode(DecodeError::WrongSize { .. }) => "wrong size",
+            Self::Decode(DecodeError::InvalidColor(_)) => "invalid color",
+            Self::Decode(DecodeError::UnknownDictionary(_)) => "unknown dictionary",
+            Self::Decode(DecodeError::TruncatedHeader(_)) => "truncated header",
+            Self::Decode(DecodeError::UnsupportedVersion(_)) => "unsupported version",
+            Self::Decode(DecodeError::WrongDimensions { .. }) => "wrong dimensions",
+            Self::Decode(DecodeError::WrongPacking { .. }) => "wrong packing",
+            Self::Decode(DecodeError::ChecksumMismatch { .. }) => "checksum mismatch",
+            Self::Unreadable(_) => "unreadable",
+        }
+    }
+}
+
+async fn check_object<T: ChunkLoaderSaver>(loader: &T, name: &str) -> Result<(), ObjectProblem> {
+    let (x, y) = ChunkCoordinates::parse_object_name(name).ok_or(ObjectProblem::BadName)?;
+    loader.board().coordinates(x, y)?;
+
+    let data = loader
+        .load_raw(name)
+        .await
+        .map_err(|err| ObjectProblem::Unreadable(format!("{:?}", err)))?;
+    Chunk::decode_for(&data, loader.board())?;
+
+    Ok(())
+}
+
+/// Check every stored object of the backend, and optionally quarantine the broken ones
+pub async fn verify<T: ChunkLoaderSaver>(loader: &T, quarantine: bool) -> Result<(), String> {
+    let names = stored_names(loader).await?;
+    let total = names.len();
+
+    let mut problems: Vec<(String, ObjectProblem)> = futures::stream::iter(names)
+        .map(|name| async move {
+            let result = check_object(loader, &name).await;
+            (name, result)
+        })
+        .buffer_unordered(COMMAND_CONCURRENCY)
+        .filter_map(|(name, result)| std::future::ready(result.err().map(|err| (name, err))))
+        .collect::<Vec<_>>()
+        .await;
+    problems.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut kinds: Vec<(&str, usize)> = Vec::new();
+    for (name, problem) in &problems {
+        println!("{}: {}", name, problem);
+
+        match kinds.iter_mut().find(|(kind, _)| *kind == problem.kind()) {
+            Some((_, count)) => *count += 1,
+            None => kinds.push((problem.kind(), 1)),
+        }
+    }
+
+    println!("{} objects, {} valid", total, total - problems.len());
+    for (kind, count) in &kinds {
+        println!("  {}: {}", kind, count);
+    }
+
+    if quarantine {
+        let mut quarantined = 0;
+        for (name, problem) in &problems {
+            // a read error could be the storage, the object might be fine
+            // and with a missing dictionary, or another chunk length or palette, the object is fine, the server setup isn't
+            if matches!(
+                problem,
+                ObjectProblem::Unreadable(_)
+                    | ObjectProblem::Decode(
+                        DecodeError::UnknownDictionary(_)
+                            | DecodeError::WrongDimensions { .. }
+                            | DecodeError::WrongPacking { .. }
+                    )
+            ) {
+                continue;
+            }
+
+            match loader.quarantine(name).await {
+                Ok(()) => quarantined += 1,
+                Err(err) => eprintln!("{}: {:?}", name, err),
+            }
+        }
+        println!(
+            "quarantined {} objects into {}/",
+            quarantined, QUARANTINE_DIR
+        );
+    }
+
+    if !problems.is_empty() {
+        return Err(format!("{} objects are broken", problems.len()));
+    }
+    Ok(())
+}
+
+/// How many of the most painted chunks [`stats`] lists
+const MOST_PAINTED_CHUNKS: usize = 10;
+
+pub async fn stats<T: ChunkLoaderSaver>(
+    loader: &T,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+) -> Result<(), String> {
+    let region_size = region_coordinates(loader.board(), top_left, bottom_right).count();
+
+    let mut stored = 0;
+    let mut broken = 0;
+    let mut color_counts = vec![0u64; loader.board().palette.colors.len()];
+    // (painted pixels, coordinates), painted is anything not the background color
+    let mut painted_chunks: Vec<(usize, ChunkCoordinates)> = Vec::new();
+
+    let mut chunks = std::pin::pin!(load_region(loader, top_left, bottom_right));
+    while let Some((coordinates, result)) = chunks.next().await {
+        let Ok(chunk) = result else {
+            broken += 1;
+            continue;
+        };
+        stored += 1;
+
+        let mut painted = 0;
+        for index in 0..loader.board().chunk_size() {
+            let color = chunk.pixel(index).u8();
+            color_counts[color as usize] += 1;
+            if color != Color::ZERO.u8() {
+                painted += 1;
+            }
+        }
+        painted_chunks.push((painted, coordinates));
+    }
+
+    painted_chunks.sort_by_key(|(painted, _)| std::cmp::Reverse(*painted));
+    let blank = painted_chunks
+        .iter()
+        .filter(|(painted, _)| *painted == 0)
+        .count();
+
+    println!("region: {:?} to {:?}", top_left, bottom_right);
+    println!("chunks in region: {}", region_size);
+    println!(
+        "chunks stored: {} ({} blank, {} broken)",
+        stored, blank, broken
+    );
+
+    let total_pixels: u64 = color_counts.iter().sum();
+    if total_pixels > 0 {
+        println!("colors:");
+        for (color, count) in color_counts.iter().enumerate() {
+            println!(
+                "  {:>2}: {:>12} ({:.2}%)",
+                color,
+                count,
+                *count as f64 / total_pixels as f64 * 100.0
+            );
+        }
+    }
+
+    println!("most painted chunks:");
+    for (painted, coordinates) in painted_chunks
+        .iter()
+        .filter(|(painted, _)| *painted > 0)
+        .take(MOST_PAINTED_CHUNKS)
+    {
+        println!(
+            "  ({}, {}): {} pixels",
+            coordinates.x(),
+            coordinates.y(),
+            painted
+        );
+    }
+
+    Ok(())
+}
+
+/// Name of the storage format of stored bytes, for the [`migrate`] report
+fn format_name(data: &[u8]) -> &'static str {
+    if data.len() == LEGACY_CHUNK_BYTE_SIZE {
+        return "raw (no format byte)";
+    }
+
+    match storage_format(data) {
+        Some(0) => "raw",
+        Some(1) => "zstd",
+        Some(2) => "lz4",
+        Some(3) => "uniform",
+        Some(4) => "rle",
+        Some(5) => "rle-zstd",
+        Some(6) => "zstd-dict",
+        _ => "unknown",
+    }
+}
+
+/// Chunks and bytes of one source format, before and after the migration
+#[derive(Debug, Default)]
+struct FormatSizes {
+    chunks: usize,
+    before: usize,
+    after: usize,
+}
+
+/// Copy every stored chunk from `source` to `destination`, re-encoding it with `compression` if given.
+///
+/// Names in the `resume` file are skipped, and every migrated chunk is appended to it.
+/// With `dry_run` nothing is written, only the size report is printed.
+pub async fn migrate<S: ChunkLoaderSaver, D: ChunkLoaderSaver>(
+    source: &S,
+    destination: &D,
+    compression: Option<&CompressionPolicy>,
+    concurrency: usize,
+    resume: Option<&Path>,
+    dry_run: bool,
+) -> Result<(), String> {
+    let mut names = stored_names(source).await?;
+    names.retain(|name| coordinates_from_file_name(source.board(), name).is_some());
+
+    let mut progress = None;
+    if let Some(resume) = resume {
+        let done = match std::fs::read_to_string(resume) {
+            Ok(text) => text.lines().map(String::from).collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                std::collections::HashSet::new()
+            }
+            Err(err) => return Err(format!("can't read {:?}: {}", resume, err)),
+        };
+        let before = names.len();
+        names.retain(|name| !done.contains(name));
+        println!("skipping {} chunks migrated before", before - names.len());
+
+        if !dry_run {
+            progress = Some(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(resume)
+                    .map_err(|err| format!("can't open {:?}: {}", resume, err))?,
+            );
+        }
+    }
+
+    let total = names.len();
+    let mut migrated = 0;
+    let mut failed = 0;
+    let mut sizes: Vec<(&'static str, FormatSizes)> = Vec::new();
+
+    let mut results = futures::stream::iter(names)
+        .map(|name| async move {
+            let result = migrate_object(source, destination, &name, compression, dry_run).await;
+            (name, result)
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    while let Some((name, result)) = results.next().await {
+        let (format, before, after) = match result {
+            Ok(sizes) => sizes,
+            Err(err) => {
+                eprintln!("{}: {}", name, err);
+                failed += 1;
+                continue;
+            }
+        };
+        migrated += 1;
+
+        let position = match sizes.iter().position(|(name, _)| *name == format) {
+            Some(position) => position,
+            None => {
+                sizes.push((format, FormatSizes::default()));
+                sizes.len() - 1
+            }
+        };
+        let format_sizes = &mut sizes[position].1;
+        format_sizes.chunks += 1;
+        format_sizes.before += before;
+        format_sizes.after += after;
+
+        if let Some(progress) = progress.as_mut() {
+            use std::io::Write;
+            writeln!(progress, "{}", name)
+                .map_err(|err| format!("can't write the progress: {}", err))?;
+        }
+        if migrated % 1000 == 0 {
+            eprintln!("{}/{} chunks", migrated, total);
+        }
+    }
+
+    let verb = if dry_run { "would migrate" } else { "migrated" };
+    println!(
+        "{} {} of {} chunks, {} failed",
+        verb, migrated, total, failed
+    );
+
+    let (mut before, mut after) = (0, 0);
+    for (format, format_sizes) in &sizes {
+        println!(
+            "  {}: {} chunks, {} -> {} bytes",
+            format, format_sizes.chunks, format_sizes.before, format_sizes.after
+        );
+        before += format_sizes.before;
+        after += format_sizes.after;
+    }
+    if before > 0 {
+        println!(
+            "total: {} -> {} bytes ({:.1}% saved)",
+            before,
+            after,
+            (1.0 - after as f64 / before as f64) * 100.0
+        );
+    }
+
+    if failed > 0 {
+        return Err(format!(
+            "{} chunks failed to migrate, `verify` the source to find out why",
+            failed
+        ));
+    }
+    Ok(())
+}
+
+/// Migrate a single object, returns the source format and the size before and after
+async fn migrate_object<S: ChunkLoaderSaver, D: ChunkLoaderSaver>(
+    source: &S,
+    destination: &D,
+    name: &str,
+    compression: Option<&CompressionPolicy>,
+    dry_run: bool,
+) -> Result<(&'static str, usize, usize), String> {
+    let data = source
+        .load_raw(name)
+        .await
+        .map_err(|err| format!("{:?}", err))?;
+    // never copy something the server can't load
+    let chunk = Chunk::decode_for(&data, source.board()).map_err(|err| err.to_string())?;
+
+    let format = format_name(&data);
+    let before = data.len();
+
+    let data = match compression {
+        Some(compression) => chunk
+            .to_storage_bytes(compression, &destination.board().dictionaries)
+            .map_err(|err| err.to_string())?,
+        None => data,
+    };
+    let after = data.len();
+
+    if !dry_run {
+        destination
+            .save_raw(name, data)
+            .await
+            .map_err(|err| format!("{:?}", err))?;
+    }
+
+    Ok((format, before, after))
+}
+
+/// Train a zstd dictionary on the stored chunks and save it as `{id}.dict` in `dir`
+pub async fn train_dictionary<T: ChunkLoaderSaver>(
+    loader: &T,
+    id: u8,
+    max_size: usize,
+    max_samples: usize,
+    dir: &Path,
+) -> Result<(), String> {
+    let path = compression::dictionary_path(dir, id);
+    // chunks compressed with the old one would not load anymore
+    if path.exists() {
+        return Err(format!(
+            "{:?} already exists, pick another id or delete it",
+            path
+        ));
+    }
+
+    let names = stored_names(loader).await?;
+    let samples: Vec<Vec<u8>> = futures::stream::iter(names)
+        .map(|name| async move {
+            let data = loader.load_raw(&name).await.ok()?;
+            Chunk::decode_for(&data, loader.board()).ok()
+        })
+        .buffer_unordered(COMMAND_CONCURRENCY)
+        // single color chunks are stored in 2 bytes, they don't need a dictionary
+        .filter_map(|chunk| {
+            std::future::ready(chunk.filter(|chunk| chunk.uniform_color().is_none()))
+        })
+        .take(max_samples)
+        .map(|chunk| chunk.to_u8vec())
+        .collect()
+        .await;
+
+    if samples.is_empty() {
+        return Err("no chunks to train on".to_string());
+    }
+
+    let dictionary = compression::train_dictionary(&samples, max_size)
+        .map_err(|err| format!("training on {} chunks failed: {}", samples.len(), err))?;
+
+    std::fs::create_dir_all(dir).map_err(|err| format!("can't create {:?}: {}", dir, err))?;
+    std::fs::write(&path, &dictionary).map_err(|err| format!("{:?}: {}", path, err))?;
+
+    // how much it helps on the chunks it was trained on
+    let mut original = 0;
+    let mut plain = 0;
+    let mut with_dictionary = 0;
+    for sample in &samples {
+        original += sample.len();
+        plain += compression::ZstdCompression::compress(sample)
+            .map_err(|err| err.to_string())?
+            .len();
+        with_dictionary += compression::ZstdDictCompression::compress(sample, &dictionary)
+            .map_err(|err| err.to_string())?
+            .len();
+    }
+
+    println!(
+        "trained dictionary {} of {} bytes on {} chunks, saved to {:?}",
+        id,
+        dictionary.len(),
+        samples.len(),
+        path
+    );
+    println!(
+        "zstd: {:.2}x, zstd with the dictionary: {:.2}x",
+        original as f64 / plain as f64,
+        original as f64 / with_dictionary as f64
+    );
+    println!("use it with `migrate --compression zstd-dict:{}`", id);
+
+    Ok(())
+}
@@ -1,27 +1,71 @@
-use std::io::{Read, Write};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
 
-use crate::types::{CHUNK_BYTE_SIZE, InnerChunk};
+use crate::types::{Board, Chunk, DecodeError};
 
 pub trait Compression {
-    fn compress(data: &[u8]) -> Result<Vec<u8>, std::io::Error>;
-    fn decompress(data: &[u8], expected_size: usize) -> Result<Vec<u8>, std::io::Error>;
+    /// Name of the codec, used in errors
+    const NAME: &'static str;
+
+    fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError>;
+    fn decompress(data: &[u8], expected_size: usize) -> Result<Vec<u8>, CompressionError>;
+}
+
+/// Why a codec could not compress or decompress
+#[derive(thiserror::Error, Debug)]
+pub enum CompressionError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Lz4(#[from] lz4_flex::block::DecompressError),
+    #[error("invalid runs, {0}")]
+    InvalidRuns(&'static str),
+    #[error("dictionary {0} is not loaded")]
+    UnknownDictionary(u8),
 }
 
 pub struct GzipCompression;
 pub struct LZ4Compression;
 pub struct ZstdCompression;
+/// Run-length encoding of the 4 bit colors, see [`RleCompression::compress`]
+pub struct RleCompression;
+/// [`RleCompression`] followed by zstd, for runs which repeat in patterns
+pub struct RleZstdCompression;
+
+/// zstd with a trained dictionary, see [`train_dictionary`]
+pub struct ZstdDictCompression;
+
+/// Extension of the dictionary files, named `{id}.dict`
+pub const DICTIONARY_EXTENSION: &str = "dict";
+
+/// Dictionaries of a board by id, the id is stored with every chunk compressed with one
+#[derive(Debug, Default)]
+pub struct Dictionaries {
+    by_id: HashMap<u8, Vec<u8>>,
+}
+
+/// zstd level of [`ZstdCompression::compress`], fast as chunks are saved often
+pub const DEFAULT_ZSTD_LEVEL: i32 = 1;
+
+/// Longest run which fits in the token itself
+const RLE_SHORT_RUN: usize = 15;
 
 impl Compression for GzipCompression {
-    fn compress(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    const NAME: &'static str = "gzip";
+
+    fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
         use flate2::Compression;
         use flate2::write::GzEncoder;
 
         let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
         encoder.write_all(data)?;
-        encoder.finish()
+        Ok(encoder.finish()?)
     }
 
-    fn decompress(compressed: &[u8], _expected_size: usize) -> Result<Vec<u8>, std::io::Error> {
+    fn decompress(compressed: &[u8], _expected_size: usize) -> Result<Vec<u8>, CompressionError> {
         use flate2::read::GzDecoder;
 
         let mut decoder = GzDecoder::new(compressed);
@@ -32,41 +76,252 @@ impl Compression for GzipCompression {
 }
 
 impl Compression for LZ4Compression {
-    fn compress(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    const NAME: &'static str = "lz4";
+
+    fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
         Ok(lz4_flex::block::compress(data))
     }
 
-    fn decompress(data: &[u8], _expected_size: usize) -> Result<Vec<u8>, std::io::Error> {
-        let mut output = [0u8; CHUNK_BYTE_SIZE];
-        lz4_flex::block::decompress_into(data, &mut output).unwrap();
-        Ok(output.into())
+    fn decompress(data: &[u8], expected_size: usize) -> Result<Vec<u8>, CompressionError> {
+        let mut output = vec![0u8; expected_size];
+        let size = lz4_flex::block::decompress_into(data, &mut output)?;
+        Ok(output[..size].into())
+    }
+}
+
+impl ZstdCompression {
+    pub fn compress_with_level(data: &[u8], level: i32) -> Result<Vec<u8>, CompressionError> {
+        Ok(zstd::bulk::compress(data, level)?)
     }
 }
 
 impl Compression for ZstdCompression {
-    fn compress(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
-        zstd::bulk::compress(data, 1)
+    const NAME: &'static str = "zstd";
+
+    fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Self::compress_with_level(data, DEFAULT_ZSTD_LEVEL)
     }
 
-    fn decompress(data: &[u8], _expected_size: usize) -> Result<Vec<u8>, std::io::Error> {
-        zstd::bulk::decompress(data, CHUNK_BYTE_SIZE)
+    fn decompress(data: &[u8], expected_size: usize) -> Result<Vec<u8>, CompressionError> {
+        Ok(zstd::bulk::decompress(data, expected_size)?)
     }
 }
+
+impl Compression for RleCompression {
+    const NAME: &'static str = "rle";
+
+    /// Every run of one color is a token byte, `(length - 1) << 4 | color` for runs up to 15.
+    ///
+    /// Longer runs have `0xF` as length nibble, followed by `length - 16` as a LEB128 varint.
+    fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        let mut output = Vec::new();
+        let mut nibbles = data.iter().flat_map(|byte| [byte >> 4, byte & 0b1111]);
+
+        let Some(mut color) = nibbles.next() else {
+            return Ok(output);
+        };
+        let mut length = 1;
+
+        for nibble in nibbles {
+            if nibble == color {
+                length += 1;
+                continue;
+            }
+            push_run(&mut output, color, length);
+            color = nibble;
+            length = 1;
+        }
+        push_run(&mut output, color, length);
+
+        Ok(output)
+    }
+
+    fn decompress(data: &[u8], expected_size: usize) -> Result<Vec<u8>, CompressionError> {
+        let expected_nibbles = expected_size * 2;
+        let mut nibbles = Vec::with_capacity(expected_nibbles);
+        let mut input = data.iter();
+
+        while let Some(token) = input.next() {
+            let color = token & 0b1111;
+            let mut length = (token >> 4) as usize + 1;
+
+            if length > RLE_SHORT_RUN {
+                let mut extra = 0usize;
+                let mut shift = 0;
+                loop {
+                    let byte = input
+                        .next()
+                        .ok_or(CompressionError::InvalidRuns("truncated run length"))?;
+                    if shift >= usize::BITS {
+                        return Err(CompressionError::InvalidRuns("run length overflows"));
+                    }
+                    extra |= ((byte & 0x7F) as usize) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                length = extra.saturating_add(RLE_SHORT_RUN + 1);
+            }
+
+            let end = nibbles
+                .len()
+                .checked_add(length)
+                .filter(|&end| end <= expected_nibbles)
+                .ok_or(CompressionError::InvalidRuns("longer than the chunk"))?;
+            nibbles.resize(end, color);
+        }
+
+        if nibbles.len() != expected_nibbles {
+            return Err(CompressionError::InvalidRuns("shorter than the chunk"));
+        }
+
+        Ok(nibbles
+            .chunks_exact(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect())
+    }
+}
+
+fn push_run(output: &mut Vec<u8>, color: u8, length: usize) {
+    if length <= RLE_SHORT_RUN {
+        output.push((((length - 1) as u8) << 4) | color);
+        return;
+    }
+
+    output.push(0xF0 | color);
+    let mut extra = length - (RLE_SHORT_RUN + 1);
+    loop {
+        let byte = (extra & 0x7F) as u8;
+        extra >>= 7;
+        if extra == 0 {
+            output.push(byte);
+            break;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+impl Compression for RleZstdCompression {
+    const NAME: &'static str = "rle-zstd";
+
+    fn compress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        ZstdCompression::compress(&RleCompression::compress(data)?)
+    }
+
+    fn decompress(data: &[u8], expected_size: usize) -> Result<Vec<u8>, CompressionError> {
+        // alternating colors are the worst case, a token for every nibble
+        let rle = zstd::bulk::decompress(data, expected_size * 2)?;
+        RleCompression::decompress(&rle, expected_size)
+    }
+}
+
+impl ZstdDictCompression {
+    pub const NAME: &'static str = "zstd-dict";
+
+    pub fn compress(data: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, CompressionError> {
+        Ok(
+            zstd::bulk::Compressor::with_dictionary(DEFAULT_ZSTD_LEVEL, dictionary)?
+                .compress(data)?,
+        )
+    }
+
+    pub fn decompress(
+        data: &[u8],
+        dictionary: &[u8],
+        expected_size: usize,
+    ) -> Result<Vec<u8>, CompressionError> {
+        Ok(zstd::bulk::Decompressor::with_dictionary(dictionary)?
+            .decompress(data, expected_size)?)
+    }
+}
+
+impl Dictionaries {
+    /// Every `{id}.dict` in the directory, a missing directory has none
+    pub fn load(dir: &Path) -> Result<Self, std::io::Error> {
+        let mut dictionaries = Self::default();
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(dictionaries),
+            Err(err) => return Err(err),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str())
+                != Some(DICTIONARY_EXTENSION)
+            {
+                continue;
+            }
+            let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            else {
+                continue;
+            };
+
+            dictionaries.insert(id, std::fs::read(&path)?);
+        }
+
+        Ok(dictionaries)
+    }
+
+    /// The dictionary with this id
+    pub fn get(&self, id: u8) -> Result<&[u8], CompressionError> {
+        self.by_id
+            .get(&id)
+            .map(Vec::as_slice)
+            .ok_or(CompressionError::UnknownDictionary(id))
+    }
+
+    pub fn contains(&self, id: u8) -> bool {
+        self.by_id.contains_key(&id)
+    }
+
+    pub fn insert(&mut self, id: u8, dictionary: Vec<u8>) {
+        self.by_id.insert(id, dictionary);
+    }
+
+    /// The ids of the dictionaries, sorted
+    pub fn ids(&self) -> Vec<u8> {
+        let mut ids: Vec<u8> = self.by_id.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+}
+
+/// Where the dictionary with this id is stored
+pub fn dictionary_path(dir: &Path, id: u8) -> PathBuf {
+    dir.join(format!("{}.{}", id, DICTIONARY_EXTENSION))
+}
+
+/// Train a zstd dictionary of at most `max_size` bytes on uncompressed chunks
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> Result<Vec<u8>, CompressionError> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
 pub trait ChunkCompression {
-    fn compress_with<C: Compression>(self) -> Result<Vec<u8>, std::io::Error>;
-    fn decompress_with<C: Compression>(data: &[u8]) -> Result<Self, std::io::Error>
+    fn compress_with<C: Compression>(self) -> Result<Vec<u8>, CompressionError>;
+    /// Decompress a chunk of the board
+    fn decompress_with<C: Compression>(data: &[u8], board: &Board) -> Result<Self, DecodeError>
     where
         Self: Sized;
 }
 
-impl<const N: usize> ChunkCompression for InnerChunk<N> {
-    fn compress_with<C: Compression>(self) -> Result<Vec<u8>, std::io::Error> {
+impl ChunkCompression for Chunk {
+    fn compress_with<C: Compression>(self) -> Result<Vec<u8>, CompressionError> {
         let data = self.to_u8vec();
         C::compress(&data)
     }
 
-    fn decompress_with<C: Compression>(data: &[u8]) -> Result<Self, std::io::Error> {
-        let decompressed = C::decompress(data, N)?;
-        Ok(Self::from(decompressed))
+    fn decompress_with<C: Compression>(data: &[u8], board: &Board) -> Result<Self, DecodeError> {
+        let expected_size = board.packing().byte_size(board.chunk_size());
+        let decompressed =
+            C::decompress(data, expected_size).map_err(|source| DecodeError::Decompression {
+                codec: C::NAME,
+                source,
+            })?;
+        Self::from_packed(decompressed, board.chunk_length, &board.palette)
     }
 }
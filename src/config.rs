@@ -0,0 +1,453 @@
+//! Settings of the server, read from a TOML file with env overrides
+//!
+//! Priority, highest first: command line flags, environment variables, the config file, defaults.
+
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use paintplayground::{
+    chunk_db::StorageBackend,
+    types::{
+        Board, Bounds, CompressionPolicy, DEFAULT_CHUNK_LENGTH, DEFAULT_CHUNKS_IN_DIRECTION,
+        DEFAULT_COMPRESSION, MAIN_BOARD, Palette,
+    },
+};
+
+/// The config file used when `--config` isn't given, it's fine if it doesn't exist
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// The board would not fit in the screenshot and tile math above this
+const MAX_CHUNKS_IN_DIRECTION: i64 = 1 << 20;
+
+/// A chunk is sent whole over the websocket, 500kb at this length
+const MAX_CHUNK_LENGTH: usize = 1000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("can't read config file {path:?}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("invalid config file {path:?}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid environment variable {name}={value:?}: {reason}")]
+    Env {
+        name: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Chunks from the center to the edge of the board, in every direction
+    pub chunks_in_direction: i64,
+    /// A rectangle, or `"infinite"`, instead of the square of `chunks_in_direction`
+    pub bounds: Option<Bounds>,
+    /// Pixels in a row and rows in a chunk, every chunk of the main board has this size
+    pub chunk_length: usize,
+    pub server: ServerConfig,
+    pub board: BoardConfig,
+    pub chunk: ChunkConfig,
+    pub storage: StorageConfig,
+    /// Colors of the board, more than 16 store a byte per pixel instead of 4 bits
+    pub palette: Palette,
+    /// Boards next to the main one, served under `/b/{name}/`
+    pub boards: Vec<NamedBoardConfig>,
+}
+
+/// A board next to the main one, with its own chunks, size and colors
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamedBoardConfig {
+    /// In the urls, letters, digits, `-` and `_`
+    pub name: String,
+    /// The bounds of the main board when neither this nor `bounds` is set
+    pub chunks_in_direction: Option<i64>,
+    pub bounds: Option<Bounds>,
+    /// The one of the main board when not set
+    pub palette: Option<Palette>,
+    /// The one of the main board when not set
+    pub chunk_length: Option<usize>,
+    /// In front of the storage paths of its chunks, `"{name}/"` when not set
+    pub storage_prefix: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Where the chunks are stored
+    pub backend: StorageBackend,
+    /// Bearer token of the admin endpoints, they are disabled without one
+    pub admin_token: Option<String>,
+}
+
+/// Settings of the [`BoardManager`](crate::board_manager::BoardManager)
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoardConfig {
+    /// ChunkManagers running at the same time, more connections are refused
+    pub max_live_chunks: usize,
+    /// Size of the channels to the BoardManager
+    pub channel_size: usize,
+}
+
+/// Settings of every [`ChunkManager`](crate::chunk_manager::ChunkManager)
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChunkConfig {
+    /// Milliseconds updates are buffered before applying and broadcasting them
+    pub clear_buffer_interval_ms: u64,
+    /// Size of the channels for pixel updates, from and to the websockets
+    pub update_channel_size: usize,
+    /// Size of the channels for chunk, ping and stats requests
+    pub request_channel_size: usize,
+    /// Seconds without changes and connections before a ChunkManager stops
+    pub idle_timeout_secs: u64,
+}
+
+/// How chunks are encoded in storage
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Directory with the trained zstd dictionaries, `{id}.dict`
+    pub dictionaries: PathBuf,
+    /// Codec of saved chunks, `"zstd:3"`, or `"smallest:lz4,zstd:19"` to keep the smallest per chunk
+    pub compression: CompressionPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            chunks_in_direction: DEFAULT_CHUNKS_IN_DIRECTION,
+            bounds: None,
+            chunk_length: DEFAULT_CHUNK_LENGTH,
+            server: ServerConfig::default(),
+            board: BoardConfig::default(),
+            chunk: ChunkConfig::default(),
+            storage: StorageConfig::default(),
+            palette: Palette::default(),
+            boards: Vec::new(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            dictionaries: PathBuf::from("dictionaries"),
+            compression: DEFAULT_COMPRESSION,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3001,
+            backend: StorageBackend::R2,
+            admin_token: None,
+        }
+    }
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        Self {
+            max_live_chunks: 100,
+            channel_size: 100,
+        }
+    }
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            clear_buffer_interval_ms: 500,
+            update_channel_size: 1000,
+            request_channel_size: 100,
+            idle_timeout_secs: 5 * 60,
+        }
+    }
+}
+
+impl ChunkConfig {
+    pub fn clear_buffer_interval(&self) -> Duration {
+        Duration::from_millis(self.clear_buffer_interval_ms)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+}
+
+impl Config {
+    /// Read the config file, then apply the environment variables.
+    ///
+    /// Without a `path` [`DEFAULT_CONFIG_PATH`] is used if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        env_override("CHUNKS_IN_DIRECTION", &mut self.chunks_in_direction)?;
+        env_override("CHUNK_LENGTH", &mut self.chunk_length)?;
+
+        env_override("BIND_ADDRESS", &mut self.server.bind)?;
+        env_override("PORT", &mut self.server.port)?;
+        env_override("STORAGE_BACKEND", &mut self.server.backend)?;
+        if let Ok(token) = std::env::var("ADMIN_TOKEN") {
+            self.server.admin_token = Some(token);
+        }
+
+        env_override("MAX_LIVE_CHUNKS", &mut self.board.max_live_chunks)?;
+        env_override("BOARD_CHANNEL_SIZE", &mut self.board.channel_size)?;
+
+        env_override(
+            "CLEAR_BUFFER_INTERVAL",
+            &mut self.chunk.clear_buffer_interval_ms,
+        )?;
+        env_override(
+            "CHUNK_UPDATE_CHANNEL_SIZE",
+            &mut self.chunk.update_channel_size,
+        )?;
+        env_override(
+            "CHUNK_REQUEST_CHANNEL_SIZE",
+            &mut self.chunk.request_channel_size,
+        )?;
+        env_override("CHUNK_IDLE_TIMEOUT", &mut self.chunk.idle_timeout_secs)?;
+
+        env_override("DICTIONARY_DIR", &mut self.storage.dictionaries)?;
+        env_override("STORAGE_COMPRESSION", &mut self.storage.compression)?;
+
+        Ok(())
+    }
+
+    /// Bounds of the main board
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+            .unwrap_or_else(|| Bounds::square(self.chunks_in_direction))
+    }
+
+    /// Every board of the server, the main board first
+    pub fn boards(&self) -> Vec<Board> {
+        let main = Board::new(
+            MAIN_BOARD.to_string(),
+            self.bounds(),
+            self.palette.clone(),
+            self.chunk_length,
+            String::new(),
+        );
+
+        std::iter::once(main)
+            .chain(self.boards.iter().map(|board| {
+                let bounds = board
+                    .bounds
+                    .or(board.chunks_in_direction.map(Bounds::square))
+                    .unwrap_or_else(|| self.bounds());
+
+                Board::new(
+                    board.name.clone(),
+                    bounds,
+                    board
+                        .palette
+                        .clone()
+                        .unwrap_or_else(|| self.palette.clone()),
+                    board.chunk_length.unwrap_or(self.chunk_length),
+                    board
+                        .storage_prefix
+                        .clone()
+                        .unwrap_or_else(|| format!("{}/", board.name)),
+                )
+            }))
+            .map(|mut board| {
+                board.compression = self.storage.compression.clone();
+                board
+            })
+            .collect()
+    }
+
+    /// Check the values which would make the server panic or misbehave later on
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        let chunks_in_direction = std::iter::once((MAIN_BOARD, Some(self.chunks_in_direction)))
+            .chain(
+                self.boards
+                    .iter()
+                    .map(|board| (board.name.as_str(), board.chunks_in_direction)),
+            );
+        for (name, chunks_in_direction) in chunks_in_direction {
+            if let Some(chunks_in_direction) = chunks_in_direction
+                && !(0..=MAX_CHUNKS_IN_DIRECTION).contains(&chunks_in_direction)
+            {
+                return invalid(format!(
+                    "chunks_in_direction of board {:?} has to be between 0 and {}, got {}",
+                    name, MAX_CHUNKS_IN_DIRECTION, chunks_in_direction
+                ));
+            }
+        }
+
+        let boards = self.boards();
+        for board in &boards {
+            board.palette.validate().map_err(ConfigError::Invalid)?;
+
+            if let Some(compression) = self.storage.compression.unsupported_codec(board.packing()) {
+                return invalid(format!(
+                    "storage.compression can't use {:?} for board {:?}, rle only stores palettes of up to 16 colors",
+                    compression, board.name
+                ));
+            }
+
+            // two pixels are packed in a byte, a row can't end halfway one
+            if !(2..=MAX_CHUNK_LENGTH).contains(&board.chunk_length)
+                || !board.chunk_length.is_multiple_of(2)
+            {
+                return invalid(format!(
+                    "chunk_length of board {:?} has to be even and between 2 and {}, got {}",
+                    board.name, MAX_CHUNK_LENGTH, board.chunk_length
+                ));
+            }
+        }
+
+        for (index, board) in boards.iter().enumerate().skip(1) {
+            let valid_name = !board.name.is_empty()
+                && board
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                return invalid(format!(
+                    "board name {:?} can only have letters, digits, - and _",
+                    board.name
+                ));
+            }
+
+            if board.storage_prefix.is_empty() {
+                return invalid(format!(
+                    "storage_prefix of board {:?} can't be empty, that's where the main board is",
+                    board.name
+                ));
+            }
+
+            for other in &boards[..index] {
+                if other.name == board.name {
+                    return invalid(format!("there are two boards named {:?}", board.name));
+                }
+
+                // S3 lists everything under a prefix, a board would list the chunks of one below it
+                let (root, other_root) = (
+                    format!("{}chunks/", board.storage_prefix),
+                    format!("{}chunks/", other.storage_prefix),
+                );
+                if root.starts_with(&other_root) || other_root.starts_with(&root) {
+                    return invalid(format!(
+                        "boards {:?} and {:?} would store their chunks in the same place",
+                        other.name, board.name
+                    ));
+                }
+            }
+        }
+
+        let at_least_one = [
+            ("board.max_live_chunks", self.board.max_live_chunks as u64),
+            ("board.channel_size", self.board.channel_size as u64),
+            (
+                "chunk.clear_buffer_interval_ms",
+                self.chunk.clear_buffer_interval_ms,
+            ),
+            (
+                "chunk.update_channel_size",
+                self.chunk.update_channel_size as u64,
+            ),
+            (
+                "chunk.request_channel_size",
+                self.chunk.request_channel_size as u64,
+            ),
+            ("chunk.idle_timeout_secs", self.chunk.idle_timeout_secs),
+        ];
+        for (name, value) in at_least_one {
+            if value == 0 {
+                return invalid(format!("{} has to be at least 1", name));
+            }
+        }
+
+        if self
+            .server
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            return invalid("server.admin_token can't be empty".to_string());
+        }
+
+        // the broadcast channel panics above this
+        if self.chunk.update_channel_size > usize::MAX / 2 {
+            return invalid(format!(
+                "chunk.update_channel_size is too big, got {}",
+                self.chunk.update_channel_size
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Replace `value` with the parsed environment variable, if it's set
+fn env_override<V>(name: &'static str, value: &mut V) -> Result<(), ConfigError>
+where
+    V: FromStr,
+    V::Err: Display,
+{
+    let Ok(text) = std::env::var(name) else {
+        return Ok(());
+    };
+
+    *value = text
+        .trim()
+        .parse()
+        .map_err(|err: V::Err| ConfigError::Env {
+            name,
+            value: text.clone(),
+            reason: err.to_string(),
+        })?;
+    Ok(())
+}
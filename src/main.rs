@@ -1,9 +1,12 @@
 use std::{
-    env,
+    collections::HashMap,
     net::SocketAddr,
-    sync::{LazyLock, atomic::AtomicUsize},
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicUsize},
 };
 
+use clap::Parser;
+
 use mimalloc::MiMalloc;
 
 // When alot of connections are made at the same time, default allocator doesn't release the memory at all.
@@ -20,44 +23,79 @@ use tracing::debug;
 
 mod board_manager;
 mod chunk_manager;
+mod cli;
+mod commands;
+mod config;
 mod router;
 mod screenshot;
+mod stats;
 #[cfg(test)]
 mod tests;
+mod tiles;
 mod ws;
 
-use paintplayground::{chunk_db::CFR2ChunkSaver, types::*};
+use cli::{Cli, Command, PlotArgs};
+use commands::with_backend;
+use paintplayground::{chunk_db::ChunkLoaderSaver, compression::Dictionaries, types::*};
 
-const CLEAR_BUFFER_INTERVAL_DEFAULT: u64 = 500;
+/// A running board, with its own BoardManager
+#[derive(Debug, Clone)]
+struct BoardHandle {
+    pub board: Arc<Board>,
+    pub board_communicator: board_manager::BoardManagerCommunicator,
+    /// rendered screenshots, see [`screenshot::ScreenshotCache`]
+    screenshot_cache: Arc<screenshot::ScreenshotCache>,
+}
 
-static CLEAR_BUFFER_INTERVAL: LazyLock<u64> = LazyLock::new(|| {
-    // Get the interval from the environment variable, or use the default
-    std::env::var("CLEAR_BUFFER_INTERVAL")
-        .unwrap_or_else(|_| {
-            info!(
-                "CLEAR_BUFFER_INTERVAL not set, using default: {}",
-                CLEAR_BUFFER_INTERVAL_DEFAULT
-            );
-            CLEAR_BUFFER_INTERVAL_DEFAULT.to_string()
-        })
-        .parse::<u64>()
-        .unwrap()
-});
+impl BoardHandle {
+    pub fn new(
+        board: Arc<Board>,
+        board_communicator: board_manager::BoardManagerCommunicator,
+    ) -> Self {
+        Self {
+            board,
+            board_communicator,
+            screenshot_cache: Arc::new(screenshot::ScreenshotCache::default()),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 struct AppState {
-    pub board_communicator: board_manager::BoardManagerCommunicator,
+    /// by name, the main board is [`MAIN_BOARD`]
+    boards: Arc<HashMap<String, BoardHandle>>,
     connections: Arc<AtomicUsize>,
+    /// set when a shutdown signal is received, so the server stops being ready
+    shutting_down: Arc<AtomicBool>,
+    /// bearer token of the admin endpoints, `None` disables them
+    admin_token: Option<Arc<str>>,
 }
 
 impl AppState {
-    pub fn new(board_communicator: board_manager::BoardManagerCommunicator) -> Self {
+    pub fn new(boards: Vec<BoardHandle>, admin_token: Option<String>) -> Self {
         Self {
-            board_communicator,
+            boards: Arc::new(
+                boards
+                    .into_iter()
+                    .map(|handle| (handle.board.name.clone(), handle))
+                    .collect(),
+            ),
+            admin_token: admin_token.map(Arc::from),
             connections: Arc::new(AtomicUsize::new(0)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// The board with this name, the main board for `None`
+    pub fn board(&self, name: Option<&str>) -> Option<&BoardHandle> {
+        self.boards.get(name.unwrap_or(MAIN_BOARD))
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
     pub fn add_connection(&self) {
         debug!("Adding connection");
         self.connections
@@ -84,25 +122,32 @@ async fn main() {
     // console_subscriber::init();
     let _ = dotenvy::dotenv();
 
-    let args: Vec<String> = env::args().collect();
-
-    if let Some(first_arg) = args.get(1) {
-        debug!("plotting");
-        if first_arg == "plot" {
-            let chunks_in_direction = *CHUNKS_IN_DIRECTION;
+    let cli = Cli::parse();
 
-            let _ = screenshot::Screenshot::from_coordinates(
-                ChunkCoordinates::new(-chunks_in_direction, chunks_in_direction).unwrap(),
-                ChunkCoordinates::new(chunks_in_direction, -chunks_in_direction).unwrap(),
-            )
-            .await
-            .save(8, "screenshot.png");
-            return;
+    let mut config = match config::Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
         }
+    };
+    if let Some(chunks_in_direction) = cli.chunks_in_direction {
+        config.chunks_in_direction = chunks_in_direction;
+        config.bounds = None;
+    }
+    if let Some(chunk_length) = cli.chunk_length {
+        config.chunk_length = chunk_length;
+    }
+    let command = cli.command.unwrap_or(Command::Serve(cli.serve));
+    if let Command::Serve(args) = &command {
+        args.apply(&mut config);
+    }
+    if let Err(err) = config.validate() {
+        eprintln!("{}", err);
+        std::process::exit(2);
     }
 
     let env_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
-    println!("env_filter: {}", env_filter);
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::new(env_filter)
@@ -112,34 +157,285 @@ async fn main() {
         .with_target(false)
         .init();
 
-    // startup_things().await; // we use r2 now
+    // chunks compressed with a dictionary can't be read without it
+    let dictionaries = match Dictionaries::load(&config.storage.dictionaries) {
+        Ok(dictionaries) => Arc::new(dictionaries),
+        Err(err) => {
+            eprintln!(
+                "can't load the dictionaries in {:?}: {}",
+                config.storage.dictionaries, err
+            );
+            std::process::exit(2);
+        }
+    };
+    if !dictionaries.ids().is_empty() {
+        info!("loaded zstd dictionaries {:?}", dictionaries.ids());
+    }
 
-    let chunk_saver = CFR2ChunkSaver::new_from_env();
+    if let Some(id) = config.storage.compression.missing_dictionary(&dictionaries) {
+        eprintln!(
+            "storage.compression uses dictionary {}, which is not in {:?}",
+            id, config.storage.dictionaries
+        );
+        std::process::exit(2);
+    }
+
+    // the storage tools work on a single board, serve goes through all of them
+    let Some(board) = configured_boards(&config, &dictionaries)
+        .into_iter()
+        .find(|board| board.name == cli.board)
+    else {
+        eprintln!("there is no board {:?} in the config", cli.board);
+        std::process::exit(2);
+    };
+    let board = Arc::new(board);
 
-    // start THE BoardManager
-    let board_manager_communicator = board_manager::BoardManager::start(chunk_saver);
+    // the board might have grown while it was served
+    if let Some(backend) = command.source_backend() {
+        let loaded = with_backend!(backend, board, |saver| load_stored_bounds(&saver).await);
+        if let Err(err) = loaded {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let result = match command {
+        Command::Serve(_) => {
+            with_backend!(config.server.backend, board, |saver| serve(
+                saver,
+                &config,
+                &dictionaries
+            )
+            .await)
+        }
+        Command::Plot(args) => plot(args, &board).await,
+        Command::Export(args) => match args.region.corners(&board) {
+            Ok((top_left, bottom_right)) => with_backend!(args.backend, board, |saver| {
+                commands::export(&saver, top_left, bottom_right, Path::new(&args.to)).await
+            }),
+            Err(err) => Err(err),
+        },
+        Command::Import(args) => with_backend!(args.backend, board, |saver| {
+            commands::import(&saver, Path::new(&args.from)).await
+        }),
+        Command::Verify(args) => with_backend!(args.backend, board, |saver| {
+            commands::verify(&saver, args.quarantine).await
+        }),
+        Command::Migrate(args) => match args.compression.as_ref().and_then(|compression| {
+            let missing = compression.missing_dictionary(&dictionaries).map(|id| {
+                format!(
+                    "dictionary {} is not in {:?}",
+                    id, config.storage.dictionaries
+                )
+            });
+            missing.or_else(|| {
+                compression.unsupported_codec(board.packing()).map(|codec| {
+                    format!(
+                        "{:?} can't store the chunks of board {:?}, its palette has more than 16 colors",
+                        codec, board.name
+                    )
+                })
+            })
+        }) {
+            Some(err) => Err(err),
+            None => with_backend!(args.from, board, |source| {
+                with_backend!(args.to, board, |destination| {
+                    commands::migrate(
+                        &source,
+                        &destination,
+                        args.compression.as_ref(),
+                        args.concurrency.into(),
+                        args.resume.as_deref(),
+                        args.dry_run,
+                    )
+                    .await
+                })
+            }),
+        },
+        Command::TrainDictionary(args) => with_backend!(args.backend, board, |saver| {
+            commands::train_dictionary(
+                &saver,
+                args.id,
+                args.size,
+                args.samples,
+                &config.storage.dictionaries,
+            )
+            .await
+        }),
+        Command::Stats(args) => match args.region.corners(&board) {
+            Ok((top_left, bottom_right)) => with_backend!(args.backend, board, |saver| {
+                commands::stats(&saver, top_left, bottom_right).await
+            }),
+            Err(err) => Err(err),
+        },
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+/// The boards of the config, reading and writing chunks with the loaded dictionaries
+fn configured_boards(config: &config::Config, dictionaries: &Arc<Dictionaries>) -> Vec<Board> {
+    config
+        .boards()
+        .into_iter()
+        .map(|mut board| {
+            board.dictionaries = dictionaries.clone();
+            board
+        })
+        .collect()
+}
+
+async fn serve<T: ChunkLoaderSaver + 'static>(
+    chunk_saver: T,
+    config: &config::Config,
+    dictionaries: &Arc<Dictionaries>,
+) -> Result<(), String> {
+    info!("{:?}", config);
+
+    let mut boards = Vec::new();
+    for board in configured_boards(config, dictionaries) {
+        let board = Arc::new(board);
+        let chunk_saver = chunk_saver.for_board(board.clone());
+        check_stored_layout(&chunk_saver).await?;
+        load_stored_bounds(&chunk_saver).await?;
+
+        // start a BoardManager for every board
+        let board_manager_communicator = board_manager::BoardManager::start(chunk_saver, config);
+        boards.push(BoardHandle::new(board, board_manager_communicator));
+    }
 
     // state of the application
-    let state = AppState::new(board_manager_communicator);
+    let state = AppState::new(boards, config.server.admin_token.clone());
 
-    let app = router::all_routes(state);
+    let app = router::all_routes(state.clone());
 
     // run it with hyper
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await.unwrap();
+    let address = SocketAddr::new(config.server.bind, config.server.port);
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .map_err(|err| format!("can't listen on {}: {}", address, err))?;
     info!("listening on {}", listener.local_addr().unwrap());
 
     axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
+    .with_graceful_shutdown(shutdown_signal(state))
     .await
-    .unwrap();
+    .map_err(|err| err.to_string())
+}
+
+/// A board never mixes chunk sizes or packings, other chunks would not load
+async fn check_stored_layout<T: ChunkLoaderSaver>(chunk_saver: &T) -> Result<(), String> {
+    let board = chunk_saver.board();
+    match paintplayground::chunk_db::stored_layout(chunk_saver).await {
+        Ok(Some(((width, height), _)))
+            if (width as usize, height as usize) != (board.chunk_length, board.chunk_length) =>
+        {
+            Err(format!(
+                "the stored chunks of board {:?} are {}x{}, but chunk_length is {}",
+                board.name, width, height, board.chunk_length
+            ))
+        }
+        Ok(Some((_, packing))) if packing != board.packing() => Err(format!(
+            "the stored chunks of board {:?} use {} bit colors, but the palette `{}` needs {}",
+            board.name,
+            packing.bits(),
+            board.palette.name,
+            board.packing().bits()
+        )),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            warn!(
+                "can't check the layout of the stored chunks of board {:?}: {:?}",
+                board.name, err
+            );
+            Ok(())
+        }
+    }
 }
 
-async fn startup_things() {
-    // create the canvas dir if it doesn't exist
-    let canvas_dir = "canvas";
-    if !std::path::Path::new(canvas_dir).exists() {
-        std::fs::create_dir(canvas_dir).unwrap();
+/// Grow the board to the bounds it grew to before a restart
+///
+/// Bounds in the config that reach further are kept, a board never shrinks.
+async fn load_stored_bounds<T: ChunkLoaderSaver>(chunk_saver: &T) -> Result<(), String> {
+    let board = chunk_saver.board();
+    let stored = chunk_saver.load_bounds().await.map_err(|err| {
+        format!(
+            "can't read the stored bounds of board {:?}: {:?}",
+            board.name, err
+        )
+    })?;
+
+    if let Some(stored) = stored {
+        let bounds = board.bounds().union(&stored);
+        board.grow(bounds).map_err(|err| err.to_string())?;
+        info!("board {:?} has the stored bounds {:?}", board.name, bounds);
     }
+    Ok(())
+}
+
+/// Saves a screenshot of the region read straight from storage.
+async fn plot(args: PlotArgs, board: &Arc<Board>) -> Result<(), String> {
+    let (top_left, bottom_right) = args.region.corners(board)?;
+
+    // the image is rendered in memory, so it gets the pixel limit of the screenshot endpoint
+    let limit = screenshot::MAX_SCREENSHOT_PIXELS;
+    let q = match args.q {
+        Some(q) => q,
+        None => screenshot::best_quality(board, top_left, bottom_right)
+            .ok_or_else(|| format!("the region is more than {} pixels even at q 1", limit))?,
+    };
+    let pixels = screenshot::region_pixels(board, top_left, bottom_right, q);
+    if pixels > limit {
+        return Err(format!(
+            "the screenshot would be {} pixels, the limit is {}. Use a smaller region or a lower --q",
+            pixels, limit
+        ));
+    }
+
+    let screenshot = with_backend!(args.backend, board, |saver| {
+        screenshot::Screenshot::from_coordinates(&saver, top_left, bottom_right).await
+    })
+    .map_err(|err| err.to_string())?;
+
+    screenshot
+        .save(q, &args.output)
+        .map_err(|err| err.to_string())?;
+    println!("saved screenshot to {}", args.output);
+
+    Ok(())
+}
+
+/// Resolves on ctrl-c or SIGTERM, marking the server as shutting down for `/readyz`
+async fn shutdown_signal(state: AppState) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install ctrl-c handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("shutdown signal received");
+    state
+        .shutting_down
+        .store(true, std::sync::atomic::Ordering::Relaxed);
 }
@@ -1,18 +1,21 @@
 use axum::{
-    Router,
-    body::Body,
-    extract::{Path, Query, State},
-    response::IntoResponse,
-    routing::get,
-};
-use serde::Deserialize;
-use tower_http::{
-    compression::CompressionLayer,
-    services::ServeDir,
+    Json, Router,
+    body::{Body, Bytes},
+    extract::{FromRequestParts, Path, Query, RawPathParams, State},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{delete, get},
 };
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use tower_http::{compression::CompressionLayer, services::ServeDir};
 
-use crate::AppState;
-use crate::{board_manager::ChunkRequest, screenshot};
+use crate::{AppState, BoardHandle};
+use crate::{
+    board_manager::{BoardManagerError, BoardStats, ChunkRequest},
+    screenshot,
+    tiles::TileCoordinates,
+};
 use paintplayground::types::*;
 
 const BUNDLED_JS: &[u8] = include_bytes!("../js/bundled.js");
@@ -22,11 +25,14 @@ pub fn all_routes(state: AppState) -> Router {
 
     Router::new()
         .route_service("/", ServeDir::new("public"))
-        .route("/js/bundled.js", get(serve_bundled_js))
-        .route("/ws/{x}/{y}", get(crate::ws::ws_handler))
-        .route("/chunk/{x}/{y}", get(get_chunk))
+        .route("/b/{board}", get(board_redirect))
+        .route("/b/{board}/", get(board_index))
         .route("/connections", get(get_connections))
-        .route("/screenshot", get(screenshot_handler))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .merge(board_routes())
+        // the same routes for the other boards, see [`CurrentBoard`]
+        .nest("/b/{board}", board_routes())
         // .layer(
         //     TraceLayer::new_for_http()
         //         .make_span_with(DefaultMakeSpan::default().include_headers(true)),
@@ -35,6 +41,66 @@ pub fn all_routes(state: AppState) -> Router {
         .with_state(state)
 }
 
+/// Routes of a single board, served for the main board and under `/b/{board}`
+fn board_routes() -> Router<AppState> {
+    Router::new()
+        .route("/js/bundled.js", get(serve_bundled_js))
+        .route("/ws/{x}/{y}", get(crate::ws::ws_handler))
+        .route("/chunk/{x}/{y}", get(get_chunk))
+        .route("/api/stats", get(get_stats))
+        .route("/api/palette", get(get_palette))
+        .route("/api/chunk/{x}/{y}", delete(reset_chunk))
+        .route("/api/bounds", get(get_bounds).put(grow_bounds))
+        .route("/screenshot", get(screenshot_handler))
+        .route("/tiles/{z}/{x}/{y}", get(tile_handler))
+}
+
+/// The board a request is for, `/b/{board}/..` or the main board
+///
+/// Unknown boards are a 404.
+pub struct CurrentBoard(pub BoardHandle);
+
+impl FromRequestParts<AppState> for CurrentBoard {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, StatusCode> {
+        let params = RawPathParams::from_request_parts(parts, state).await.ok();
+        let name = params
+            .as_ref()
+            .and_then(|params| params.iter().find(|(key, _)| *key == "board"))
+            .map(|(_, value)| value);
+
+        state
+            .board(name)
+            .cloned()
+            .map(CurrentBoard)
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+}
+
+/// `{x}/{y}` of a chunk, as a struct so the `{board}` parameter is ignored
+#[derive(Deserialize)]
+pub struct ChunkPath {
+    pub x: i64,
+    pub y: i64,
+}
+
+/// The page of a board, the scripts are loaded relative to it
+async fn board_index(CurrentBoard(_): CurrentBoard) -> Result<Html<String>, StatusCode> {
+    tokio::fs::read_to_string("public/index.html")
+        .await
+        .map(Html)
+        .map_err(|err| {
+            error!("reading index.html failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// `/b/{board}` to `/b/{board}/`, otherwise `./js/bundled.js` points outside of the board
+async fn board_redirect(Path(board): Path<String>, CurrentBoard(_): CurrentBoard) -> Redirect {
+    Redirect::permanent(&format!("/b/{}/", board))
+}
+
 async fn serve_bundled_js() -> impl IntoResponse {
     axum::response::Response::builder()
         .header("Content-Type", "application/javascript")
@@ -50,27 +116,307 @@ async fn get_connections(State(state): State<AppState>) -> String {
     )
 }
 
-#[axum::debug_handler]
-async fn get_chunk(
-    Path((x, y)): Path<(i64, i64)>,
+#[derive(Serialize)]
+struct StatsResponse {
+    connections: usize,
+    #[serde(flatten)]
+    board: BoardStats,
+}
+
+async fn get_stats(
+    CurrentBoard(board): CurrentBoard,
+    State(state): State<AppState>,
+) -> Result<Json<StatsResponse>, StatusCode> {
+    let board = board.board_communicator.get_stats().await.map_err(|err| {
+        error!("collecting stats failed: {}", err);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+
+    Ok(Json(StatsResponse {
+        connections: state.connections.load(std::sync::atomic::Ordering::Relaxed),
+        board,
+    }))
+}
+
+#[derive(Serialize)]
+struct PaletteResponse {
+    #[serde(flatten)]
+    palette: Palette,
+    /// bits per pixel in chunks and updates
+    bits: usize,
+}
+
+/// The colors of the board, a [`Color`] is an index in `colors`
+async fn get_palette(CurrentBoard(board): CurrentBoard) -> Json<PaletteResponse> {
+    Json(PaletteResponse {
+        palette: board.board.palette.clone(),
+        bits: board.board.packing().bits(),
+    })
+}
+
+/// Delete a stored chunk, clients connected to it get the blank chunk
+///
+/// Needs `Authorization: Bearer <admin_token>`, without a configured token it doesn't exist.
+async fn reset_chunk(
+    Path(ChunkPath { x, y }): Path<ChunkPath>,
+    CurrentBoard(board): CurrentBoard,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Vec<u8>, impl IntoResponse> {
+) -> StatusCode {
+    if let Err(status) = check_admin(&state, &headers) {
+        return status;
+    }
+
+    let Ok(coordinates) = board.board.coordinates(x, y) else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    match board.board_communicator.reset_chunk(coordinates).await {
+        Ok(()) => {
+            info!("chunk {:?} was reset", coordinates);
+            StatusCode::NO_CONTENT
+        }
+        Err(BoardManagerError::Resetting) => StatusCode::CONFLICT,
+        Err(err) => {
+            error!("resetting chunk {:?} failed: {}", coordinates, err);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// The chunks of the board, they can grow while it runs
+async fn get_bounds(CurrentBoard(board): CurrentBoard) -> Json<Bounds> {
+    Json(board.board.bounds())
+}
+
+/// Grow the board to the bounds in the body, the chunks that come in start blank
+///
+/// Needs `Authorization: Bearer <admin_token>` like [`reset_chunk`].
+/// Bounds which don't contain the current ones are refused. The grown bounds are stored
+/// with the chunks of the board, and used instead of the config after a restart.
+async fn grow_bounds(
+    CurrentBoard(board): CurrentBoard,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(bounds): Json<Bounds>,
+) -> Response {
+    if let Err(status) = check_admin(&state, &headers) {
+        return status.into_response();
+    }
+
+    match board.board_communicator.grow_bounds(bounds).await {
+        Ok(()) => {
+            info!("board {:?} grew to {:?}", board.board.name, bounds);
+            Json(bounds).into_response()
+        }
+        Err(err @ BoardManagerError::Shrink(_)) => {
+            (StatusCode::CONFLICT, err.to_string()).into_response()
+        }
+        Err(err) => {
+            error!("growing board {:?} failed: {}", board.board.name, err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Whether the request has the admin token, the admin endpoints don't exist without one
+fn check_admin(state: &AppState, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(admin_token) = &state.admin_token else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token.as_bytes() == admin_token.as_bytes());
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    Ok(())
+}
+
+/// The board whose BoardManager isn't answering, if any
+async fn unresponsive_board(state: &AppState) -> Option<&str> {
+    for (name, board) in state.boards.iter() {
+        if !board.board_communicator.ping().await {
+            return Some(name);
+        }
+    }
+    None
+}
+
+/// The process is alive and the BoardManagers are answering
+async fn healthz(State(state): State<AppState>) -> impl IntoResponse {
+    match unresponsive_board(&state).await {
+        None => (StatusCode::OK, "ok".to_string()),
+        Some(name) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("board manager of {:?} is not responding", name),
+        ),
+    }
+}
+
+/// The server can take traffic: not shutting down, BoardManagers alive and storage reachable
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    if state.is_shutting_down() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "shutting down".to_string());
+    }
+
+    if let Some(name) = unresponsive_board(&state).await {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("board manager of {:?} is not responding", name),
+        );
+    }
+
+    for (name, board) in state.boards.iter() {
+        if let Err(err) = board.board_communicator.probe_storage().await {
+            warn!("readiness storage probe of {:?} failed: {}", name, err);
+            return (StatusCode::SERVICE_UNAVAILABLE, err.to_string());
+        }
+    }
+
+    (StatusCode::OK, "ready".to_string())
+}
+
+/// Where [`get_chunk`] reads the chunk from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ChunkSource {
+    /// the ChunkManager when it runs, with the changes not saved yet
+    #[default]
+    Live,
+    /// as it is saved
+    Stored,
+}
+
+/// How [`get_chunk`] returns the chunk
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ChunkFormat {
+    /// the packed pixels
+    #[default]
+    Raw,
+    /// as it is stored, with its header, see [`Chunk::to_storage_bytes`]
+    Compressed,
+    Png,
+    /// the color of every pixel, row by row
+    Json,
+}
+
+#[derive(Deserialize)]
+struct ChunkQuery {
+    #[serde(default)]
+    source: ChunkSource,
+    #[serde(default)]
+    format: ChunkFormat,
+    /// pixels per pixel of the png
+    q: Option<u8>,
+}
+
+/// A chunk, `/chunk/{x}/{y}?source=live&format=raw`
+#[axum::debug_handler(state = AppState)]
+async fn get_chunk(
+    Path(ChunkPath { x, y }): Path<ChunkPath>,
+    Query(ChunkQuery { source, format, q }): Query<ChunkQuery>,
+    CurrentBoard(board): CurrentBoard,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
     // check based on the user if they are allowed to get these coordinates
-    let Ok(coordinates) = ChunkCoordinates::new(x, y) else {
-        return Err(axum::http::StatusCode::NOT_FOUND);
+    let Ok(coordinates) = board.board.coordinates(x, y) else {
+        return Err(StatusCode::NOT_FOUND);
     };
-    // todo perhaps have  a _raw version that gets the Vec<u8> directly
+    let q = q.unwrap_or(1).clamp(1, 8);
 
-    let Some(chunk) = state
+    let request = match source {
+        ChunkSource::Live => ChunkRequest::Live,
+        ChunkSource::Stored => ChunkRequest::Storage,
+    };
+    let Some(chunk) = board
         .board_communicator
-        .get_chunk(coordinates, ChunkRequest::Storage)
+        .get_chunk(coordinates, request)
         .await
     else {
-        return Err(axum::http::StatusCode::NOT_FOUND);
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    // the live chunk is the stored one until it changes
+    let last_modified = match (source, board.board_communicator.last_change(coordinates)) {
+        (ChunkSource::Live, Some(changed)) => Some(changed),
+        _ => board
+            .board_communicator
+            .chunk_metadata(coordinates)
+            .await
+            .map_err(|err| {
+                error!(
+                    "reading metadata of chunk {:?} failed: {}",
+                    coordinates, err
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .and_then(|metadata| metadata.last_modified),
+    };
+
+    // chunks stored before the compression of the board changed keep their bytes, so the bytes decide the ETag
+    let compressed = match format {
+        ChunkFormat::Compressed => Some(match chunk.stored_bytes() {
+            Some(stored) => stored.to_vec(),
+            None => chunk
+                .clone()
+                .to_storage_bytes(&board.board.compression, &board.board.dictionaries)
+                .map_err(|err| {
+                    error!("compressing chunk {:?} failed: {}", coordinates, err);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?,
+        }),
+        _ => None,
+    };
+
+    let etag = chunk_etag(&chunk, format, q, compressed.as_deref());
+    let not_modified = match headers.contains_key(header::IF_NONE_MATCH) {
+        true => if_none_match(&headers, &etag),
+        false => last_modified.is_some_and(|time| if_modified_since(&headers, time)),
+    };
+
+    let mut response = Response::builder()
+        .header("Cache-Control", "no-cache")
+        .header("ETag", &etag);
+    if let Some(time) = last_modified {
+        response = response.header("Last-Modified", httpdate::fmt_http_date(time));
+    }
+
+    if not_modified {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let (content_type, body) = match (format, compressed) {
+        (ChunkFormat::Compressed, Some(compressed)) => ("application/octet-stream", compressed),
+        (ChunkFormat::Raw | ChunkFormat::Compressed, _) => {
+            ("application/octet-stream", chunk.data())
+        }
+        (ChunkFormat::Png, _) => {
+            let screenshot =
+                screenshot::Screenshot::from_chunks(vec![vec![Some(chunk)]], &board.board);
+            ("image/png", screenshot.create_png(q))
+        }
+        (ChunkFormat::Json, _) => {
+            let colors = (0..board.board.chunk_size())
+                .map(|index| chunk.pixel(index).u8().to_string())
+                .collect::<Vec<_>>();
+            ("application/json", format!("[{}]", colors.join(",")).into())
+        }
     };
 
-    // return the chunk in binary
-    return Ok(chunk.into());
+    Ok(response
+        .header("Content-Type", content_type)
+        .header("Content-Length", body.len().to_string())
+        .body(Body::from(body))
+        .unwrap())
 }
 
 #[derive(Deserialize)]
@@ -82,43 +428,264 @@ struct ScreenshotQuery {
     q: Option<u8>,
 }
 
-#[axum::debug_handler]
+#[axum::debug_handler(state = AppState)]
 async fn screenshot_handler(
     Query(params): Query<ScreenshotQuery>,
-    State(state): State<AppState>,
-) -> Result<impl IntoResponse, impl IntoResponse> {
+    CurrentBoard(board): CurrentBoard,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
     let ScreenshotQuery { x, y, x2, y2, q } = params;
     let (x2, y2) = (x2.unwrap_or(x), y2.unwrap_or(y));
-    let q = q.unwrap_or(4).min(8); // max quality
+    let q = q.unwrap_or(4).clamp(1, 8); // max quality
 
     if x > x2 || y < y2 {
         debug!("Invalid coordinates: x={} y={} x2={} y2={}", x, y, x2, y2);
-        return Err(axum::http::StatusCode::BAD_REQUEST);
+        return Err(StatusCode::BAD_REQUEST.into_response());
     }
 
-    let Ok(top_left) = ChunkCoordinates::new(x, y) else {
+    let Ok(top_left) = board.board.coordinates(x, y) else {
         debug!("top_left not found");
-        return Err(axum::http::StatusCode::NOT_FOUND);
+        return Err(StatusCode::NOT_FOUND.into_response());
     };
 
-    let Ok(bottom_right) = ChunkCoordinates::new(x2, y2) else {
+    let Ok(bottom_right) = board.board.coordinates(x2, y2) else {
         debug!("bottom_right not found");
-        return Err(axum::http::StatusCode::NOT_FOUND);
+        return Err(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let pixels = screenshot::region_pixels(&board.board, top_left, bottom_right, q);
+    if pixels > screenshot::MAX_SCREENSHOT_PIXELS {
+        debug!("screenshot too large: {} pixels", pixels);
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "screenshot would be {} pixels, the limit is {} pixels. Use a smaller region or a lower q",
+                pixels,
+                screenshot::MAX_SCREENSHOT_PIXELS
+            ),
+        )
+            .into_response());
+    }
+
+    let key = screenshot::ScreenshotKey {
+        top_left,
+        bottom_right,
+        quality: q,
+    };
+    let version = board
+        .board_communicator
+        .region_version(top_left, bottom_right);
+    let etag = screenshot_etag(&key, version);
+
+    if if_none_match(&headers, &etag) {
+        return Ok(axum::response::Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header("ETag", &etag)
+            .header("Cache-Control", "no-cache")
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    // big screenshots are encoded while being sent, and not cached
+    if pixels > screenshot::STREAM_SCREENSHOT_PIXELS {
+        let body = streamed_screenshot(board, top_left, bottom_right, q);
+
+        return Ok(axum::response::Response::builder()
+            .header("Content-Type", "image/png")
+            .header("Cache-Control", "no-cache")
+            .header("ETag", etag)
+            .body(body)
+            .unwrap());
+    }
+
+    let png_buffer = match board.screenshot_cache.get(&key, version) {
+        Some(png_buffer) => png_buffer,
+        None => {
+            let chunks = board
+                .board_communicator
+                .get_screenshot_chunks(top_left, bottom_right)
+                .await
+                .map_err(|err| {
+                    error!("fetching screenshot chunks failed: {:?}", err);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                })?;
+
+            let screenshot = screenshot::Screenshot::from_chunks(chunks, &board.board);
+            let png_buffer = Bytes::from(screenshot.create_png(q));
+
+            board
+                .screenshot_cache
+                .insert(key, version, png_buffer.clone());
+            png_buffer
+        }
+    };
+
+    Ok(axum::response::Response::builder()
+        .header("Content-Type", "image/png")
+        .header("Content-Length", png_buffer.len().to_string())
+        // the client has to revalidate, which is cheap with the ETag
+        .header("Cache-Control", "no-cache")
+        .header("ETag", etag)
+        .body(Body::from(png_buffer))
+        .unwrap())
+}
+
+/// A png body which is encoded while it is sent, fetching one row of chunks at a time
+fn streamed_screenshot(
+    board: BoardHandle,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+    quality: u8,
+) -> Body {
+    let x_chunks = (bottom_right.x() - top_left.x() + 1) as usize;
+    let y_chunks = (top_left.y() - bottom_right.y() + 1) as usize;
+
+    // small buffers, so memory stays bounded when the client is slow
+    let (rows_tx, rows_rx) = mpsc::channel(2);
+    let (body_tx, body_rx) = mpsc::channel(8);
+
+    let BoardHandle {
+        board,
+        board_communicator,
+        ..
+    } = board;
+    let encoded_board = board.clone();
+
+    tokio::spawn(async move {
+        for y in (bottom_right.y()..=top_left.y()).rev() {
+            let (Ok(row_left), Ok(row_right)) = (
+                board.coordinates(top_left.x(), y),
+                board.coordinates(bottom_right.x(), y),
+            ) else {
+                break;
+            };
+
+            let row = match board_communicator
+                .get_screenshot_chunks(row_left, row_right)
+                .await
+            {
+                Ok(mut rows) => rows.pop().unwrap_or_default(),
+                Err(err) => {
+                    error!("fetching screenshot row failed: {:?}", err);
+                    break;
+                }
+            };
+
+            // the encoder stopped, most likely the client is gone
+            if rows_tx.send(row).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio::task::spawn_blocking(move || {
+        let output = screenshot::BodyWriter::new(body_tx.clone());
+        let result = screenshot::Screenshot::stream_png(
+            rows_rx,
+            x_chunks,
+            y_chunks,
+            quality,
+            &encoded_board,
+            output,
+        );
+
+        if let Err(err) = result {
+            debug!("streaming screenshot stopped: {}", err);
+            // ends the body with an error, so the client knows the png is incomplete
+            let _ = body_tx.blocking_send(Err(std::io::Error::other(err)));
+        }
+    });
+
+    Body::from_stream(futures::stream::unfold(body_rx, |mut body_rx| async move {
+        body_rx.recv().await.map(|piece| (piece, body_rx))
+    }))
+}
+
+/// Strong ETag of a screenshot, changes when a chunk of the region changes
+fn screenshot_etag(key: &screenshot::ScreenshotKey, region_version: u64) -> String {
+    let mut hasher = DefaultHasher::new();
+    (key, region_version).hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Strong ETag of a chunk in a format, changes when one of its pixels changes
+///
+/// `compressed` is the body of [`ChunkFormat::Compressed`], the same pixels can be compressed differently.
+fn chunk_etag(
+    chunk: &Chunk,
+    format: ChunkFormat,
+    quality: u8,
+    compressed: Option<&[u8]>,
+) -> String {
+    let mut hasher = DefaultHasher::new();
+    (format, chunk.data()).hash(&mut hasher);
+    match format {
+        ChunkFormat::Png => quality.hash(&mut hasher),
+        ChunkFormat::Compressed => compressed.hash(&mut hasher),
+        ChunkFormat::Raw | ChunkFormat::Json => {}
+    }
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Whether nothing changed since the `If-Modified-Since` header, to the second
+fn if_modified_since(headers: &HeaderMap, last_modified: std::time::SystemTime) -> bool {
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .is_some_and(|since| {
+            // http dates have no fractions of seconds
+            let since = since + std::time::Duration::from_secs(1);
+            last_modified < since
+        })
+}
+
+/// Whether the `If-None-Match` header matches the ETag
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
     };
 
-    let chunks = state
+    value.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+#[derive(Deserialize)]
+struct TilePath {
+    z: u8,
+    x: i64,
+    y: String,
+}
+
+/// Map tile, `/tiles/{z}/{x}/{y}.png`
+///
+/// zoom 0 is a single chunk, every zoom level higher covers 2x2 tiles of the one below
+async fn tile_handler(
+    Path(TilePath { z, x, y }): Path<TilePath>,
+    CurrentBoard(board): CurrentBoard,
+) -> Result<impl IntoResponse, StatusCode> {
+    let Some(y) = y.strip_suffix(".png").and_then(|y| y.parse().ok()) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let Some(tile) = TileCoordinates::new(&board.board.bounds(), z, x, y) else {
+        debug!("tile not on the board: z={} x={} y={}", z, x, y);
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let png_buffer = board
         .board_communicator
-        .get_screenshot_chunks(top_left, bottom_right)
+        .get_tile(tile)
         .await
         .map_err(|err| {
-            error!("fetching screenshot chunks failed: {:?}", err);
-            &axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            error!("rendering tile failed: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    let screenshot = screenshot::Screenshot::from_chunks(chunks);
-
-    let png_buffer = screenshot.create_png(q);
-
     Ok(axum::response::Response::builder()
         .header("Content-Type", "image/png")
         .header("Content-Length", png_buffer.len().to_string())
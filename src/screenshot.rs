@@ -1,53 +1,104 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::Write,
+    sync::Mutex,
+};
+
+use axum::body::Bytes;
+
 use crate::{Chunk, ChunkCoordinates};
 
+use futures::{StreamExt, TryStreamExt};
 use paintplayground::{
-    chunk_db::{ChunkLoaderSaver, SimpleToFileSaver},
+    chunk_db::{ChunkLoaderSaver, LIST_PAGE_SIZE, list_objects},
     types::*,
 };
 
+/// How many chunks are loaded from storage at the same time for a screenshot
+const SCREENSHOT_LOAD_CONCURRENCY: usize = 32;
+
+/// Regions with more chunks than this are refused by [`Screenshot::from_coordinates`]
+pub const MAX_SCREENSHOT_CHUNKS: u64 = 100_000;
+
+#[derive(Debug, thiserror::Error)]
+#[error("the region has {0} chunks, a screenshot can have at most {MAX_SCREENSHOT_CHUNKS}")]
+pub struct TooManyChunksError(pub u128);
+
 pub struct Screenshot {
     chunks: Vec<Vec<Option<Chunk>>>,
+    /// palette of the board the chunks are from
+    palette: Palette,
+    /// of the chunks of the board, missing chunks are blank
+    chunk_length: usize,
 }
 
 impl Screenshot {
-    /// create screenshot from two corner [`ChunkCoordinates`]
+    /// create screenshot from two corner [`ChunkCoordinates`], loading the chunks from storage
     ///
-    /// todo use [`AppState`] to get the chunks, instead of filesaver
-    pub async fn from_coordinates(
+    /// Regions above [`MAX_SCREENSHOT_CHUNKS`] are refused.
+    pub async fn from_coordinates<T: ChunkLoaderSaver>(
+        loader: &T,
         top_left: ChunkCoordinates,
         bottom_right: ChunkCoordinates,
-    ) -> Self {
-        let loader = SimpleToFileSaver::new();
-
+    ) -> Result<Self, TooManyChunksError> {
         let min_x = top_left.x().min(bottom_right.x());
         let max_x = top_left.x().max(bottom_right.x());
         let min_y = bottom_right.y().min(top_left.y());
         let max_y = bottom_right.y().max(top_left.y());
 
-        let width = (max_x - min_x + 1) as usize;
-        let height = (max_y - min_y + 1) as usize;
-
-        let mut chunks = Vec::with_capacity(height);
-
-        for y in (min_y..=max_y).rev() {
-            let mut row = Vec::with_capacity(width);
-            for x in min_x..=max_x {
-                let coordinate = ChunkCoordinates::new(x, y).unwrap();
-                if let Ok(chunk) = loader.load_chunk(coordinate, false).await {
-                    row.push(Some(chunk));
-                } else {
-                    row.push(None);
-                }
-            }
-            chunks.push(row);
+        // on an infinite board the difference doesn't fit in an i64
+        let side = |min: i64, max: i64| (max as i128 - min as i128 + 1) as u128;
+        let region_chunks = side(min_x, max_x).saturating_mul(side(min_y, max_y));
+        if region_chunks > MAX_SCREENSHOT_CHUNKS as u128 {
+            return Err(TooManyChunksError(region_chunks));
         }
+        let width = side(min_x, max_x) as usize;
+        let height = side(min_y, max_y) as usize;
+
+        // for big regions listing what is stored takes fewer requests than trying every coordinate
+        let stored: Option<HashSet<ChunkCoordinates>> = if width * height > LIST_PAGE_SIZE {
+            list_objects(loader, LIST_PAGE_SIZE)
+                .try_filter_map(|object| std::future::ready(Ok(object.coordinates(loader.board()))))
+                .try_collect()
+                .await
+                .ok()
+        } else {
+            None
+        };
+        let stored = &stored;
+
+        // top row first, left to right
+        let coordinates = (min_y..=max_y)
+            .rev()
+            .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)));
+
+        let loaded: Vec<Option<Chunk>> = futures::stream::iter(coordinates)
+            .map(|(x, y)| async move {
+                let coordinate = loader.board().coordinates(x, y).ok()?;
+                if stored
+                    .as_ref()
+                    .is_some_and(|stored| !stored.contains(&coordinate))
+                {
+                    return None;
+                }
+                loader.load_chunk(coordinate, false).await.ok()
+            })
+            .buffered(SCREENSHOT_LOAD_CONCURRENCY)
+            .collect()
+            .await;
+
+        let chunks = loaded.chunks(width).map(|row| row.to_vec()).collect();
 
-        Self { chunks }
+        Ok(Self::from_chunks(chunks, loader.board()))
     }
 
-    /// create [`Screenshot`] from chunks
-    pub fn from_chunks(chunks: Vec<Vec<Option<Chunk>>>) -> Self {
-        Self { chunks }
+    /// create [`Screenshot`] from chunks of the board
+    pub fn from_chunks(chunks: Vec<Vec<Option<Chunk>>>, board: &Board) -> Self {
+        Self {
+            chunks,
+            palette: board.palette.clone(),
+            chunk_length: board.chunk_length,
+        }
     }
 
     /// generate a rbg8 buffer from the chunks,
@@ -57,7 +108,8 @@ impl Screenshot {
         let y_chunks = self.chunks.len();
 
         let scale = quality.max(1) as usize;
-        let chunk_scaled = CHUNK_LENGTH * scale;
+        let chunk_length = self.chunk_length;
+        let chunk_scaled = chunk_length * scale;
         let img_width = (x_chunks * chunk_scaled) as u32;
         let img_height = (y_chunks * chunk_scaled) as u32;
 
@@ -68,8 +120,8 @@ impl Screenshot {
             .iter()
             .enumerate()
             .for_each(|(chunk_y, chunk_row)| {
-                for row_in_chunk in 0..CHUNK_LENGTH {
-                    let base_y = (chunk_y * CHUNK_LENGTH + row_in_chunk) * scale;
+                for row_in_chunk in 0..chunk_length {
+                    let base_y = (chunk_y * chunk_length + row_in_chunk) * scale;
 
                     chunk_row
                         .iter()
@@ -77,13 +129,13 @@ impl Screenshot {
                         .for_each(|(chunk_x, maybe_chunk)| {
                             let row_colors = match maybe_chunk {
                                 Some(chunk) => chunk.row_of_colors(row_in_chunk),
-                                None => vec![Color::Zero; CHUNK_LENGTH],
+                                None => vec![Color::ZERO; chunk_length],
                             };
 
                             let base_x = chunk_x * chunk_scaled;
 
                             for (x, color) in row_colors.iter().enumerate() {
-                                let (r, g, b) = color.to_rgb();
+                                let Rgb(r, g, b) = self.palette.rgb(*color);
 
                                 // scaling, how fun....
                                 for dy in 0..scale {
@@ -106,87 +158,53 @@ impl Screenshot {
     }
 
     // you can index the buffer with colour data for smaller files
-    // as we only have 16 colours, this is great
-    pub fn generate_indexed_buffer_4bit(&self, quality: u8) -> (Vec<u8>, u32, u32) {
+    // with up to 16 colours this is only 4 bits a pixel, great
+    pub fn generate_indexed_buffer(&self, quality: u8) -> (Vec<u8>, u32, u32) {
         let x_chunks = self.chunks[0].len();
         let y_chunks = self.chunks.len();
 
         let scale = quality.max(1) as usize;
-        let chunk_scaled = CHUNK_LENGTH * scale;
+        let chunk_scaled = self.chunk_length * scale;
         let img_width = (x_chunks * chunk_scaled) as u32;
         let img_height = (y_chunks * chunk_scaled) as u32;
 
-        let buffer_size = ((img_width as usize * img_height as usize) + 1) / 2;
-        let mut buffer = vec![0u8; buffer_size];
-
-        self.chunks
-            .iter()
-            .enumerate()
-            .for_each(|(chunk_y, chunk_row)| {
-                for row_in_chunk in 0..CHUNK_LENGTH {
-                    let base_y = (chunk_y * CHUNK_LENGTH + row_in_chunk) * scale;
-
-                    chunk_row
-                        .iter()
-                        .enumerate()
-                        .for_each(|(chunk_x, maybe_chunk)| {
-                            let row_colors = match maybe_chunk {
-                                Some(chunk) => chunk.row_of_colors(row_in_chunk),
-                                None => vec![Color::Zero; CHUNK_LENGTH],
-                            };
-
-                            let base_x = chunk_x * chunk_scaled;
-
-                            for (x, color) in row_colors.iter().enumerate() {
-                                let color_index = color.to_index();
-
-                                // scaling
-                                for dy in 0..scale {
-                                    let y_offset = base_y + dy;
-                                    for dx in 0..scale {
-                                        let x_offset = base_x + x * scale + dx;
-                                        let pixel_pos = y_offset * img_width as usize + x_offset;
-
-                                        // Calculate byte position and bit position within byte
-                                        let byte_pos = pixel_pos / 2;
-                                        let is_high_nibble = pixel_pos & 1 == 0;
-
-                                        // Update the right nibble of the byte
-                                        if is_high_nibble {
-                                            // High nibble (first 4 bits)
-                                            buffer[byte_pos] =
-                                                (buffer[byte_pos] & 0x0F) | (color_index << 4);
-                                        } else {
-                                            // Low nibble (last 4 bits)
-                                            buffer[byte_pos] =
-                                                (buffer[byte_pos] & 0xF0) | color_index;
-                                        }
-                                    }
-                                }
-                            }
-                        });
+        let packing = self.palette.packing();
+        let line_size = indexed_line_size(img_width, packing);
+        let mut buffer = Vec::with_capacity(line_size * img_height as usize);
+        let mut line = vec![0u8; line_size];
+
+        for chunk_row in &self.chunks {
+            for row_in_chunk in 0..self.chunk_length {
+                indexed_scanline(
+                    chunk_row,
+                    row_in_chunk,
+                    self.chunk_length,
+                    scale,
+                    packing,
+                    &mut line,
+                );
+
+                // scaling, the same line repeated
+                for _ in 0..scale {
+                    buffer.extend_from_slice(&line);
                 }
-            });
+            }
+        }
 
         (buffer, img_width, img_height)
     }
 
     pub fn create_png(self, quality: u8) -> Vec<u8> {
-        let (indexed_buffer, width, height) = self.generate_indexed_buffer_4bit(quality);
+        let (indexed_buffer, width, height) = self.generate_indexed_buffer(quality);
         let mut png_buffer = Vec::new();
         {
-            let mut encoder = png::Encoder::new(&mut png_buffer, width, height);
-            encoder.set_color(png::ColorType::Indexed);
-            encoder.set_depth(png::BitDepth::Four);
-
-            encoder.set_filter(png::FilterType::NoFilter);
-            encoder.set_compression(png::Compression::Best);
-
-            let palette: Vec<u8> = Color::all_colors_rgb()
-                .iter()
-                .flat_map(|(r, g, b)| vec![*r, *g, *b])
-                .collect();
-            encoder.set_palette(&palette);
+            let encoder = indexed_png_encoder(
+                &mut png_buffer,
+                width,
+                height,
+                png::Compression::Best,
+                &self.palette,
+            );
 
             let mut writer = encoder.write_header().unwrap();
             writer.write_image_data(&indexed_buffer).unwrap();
@@ -195,6 +213,62 @@ impl Screenshot {
         png_buffer
     }
 
+    /// Encode a png from rows of chunks of the board as they arrive, top row first.
+    ///
+    /// Only the current row of chunks and a single scanline are kept in memory,
+    /// the encoded bytes go straight into `output`.
+    /// Blocking, run it with [`tokio::task::spawn_blocking`].
+    pub fn stream_png<W: Write>(
+        mut chunk_rows: mpsc::Receiver<Vec<Option<Chunk>>>,
+        x_chunks: usize,
+        y_chunks: usize,
+        quality: u8,
+        board: &Board,
+        output: W,
+    ) -> Result<(), png::EncodingError> {
+        let scale = quality.max(1) as usize;
+        let chunk_length = board.chunk_length;
+        let chunk_scaled = chunk_length * scale;
+        let img_width = (x_chunks * chunk_scaled) as u32;
+        let img_height = (y_chunks * chunk_scaled) as u32;
+        let packing = board.packing();
+
+        let encoder = indexed_png_encoder(
+            output,
+            img_width,
+            img_height,
+            png::Compression::Fast,
+            &board.palette,
+        );
+        let mut writer = encoder.write_header()?;
+        let mut stream = writer.stream_writer()?;
+
+        let mut line = vec![0u8; indexed_line_size(img_width, packing)];
+        for _ in 0..y_chunks {
+            let Some(chunk_row) = chunk_rows.blocking_recv() else {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            };
+
+            for row_in_chunk in 0..chunk_length {
+                indexed_scanline(
+                    &chunk_row,
+                    row_in_chunk,
+                    chunk_length,
+                    scale,
+                    packing,
+                    &mut line,
+                );
+                for _ in 0..scale {
+                    stream.write_all(&line)?;
+                }
+            }
+        }
+
+        stream.finish()?;
+        // writes the end of the png and flushes the output
+        writer.finish()
+    }
+
     /// save chunks screenshot to file
     pub fn save(&self, quality: u8, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
         let (buffer, img_width, img_height) = self.generate_buffer(quality);
@@ -210,3 +284,231 @@ impl Screenshot {
         Ok(())
     }
 }
+
+/// Bytes of a scanline of palette indices, as many bits as the board packs a pixel in
+fn indexed_line_size(img_width: u32, packing: Packing) -> usize {
+    (img_width as usize * packing.bits()).div_ceil(8)
+}
+
+/// Write one scanline of a row of chunks as 4 or 8 bit palette indices
+///
+/// `line` has to fit the whole row of chunks scaled
+fn indexed_scanline(
+    chunk_row: &[Option<Chunk>],
+    row_in_chunk: usize,
+    chunk_length: usize,
+    scale: usize,
+    packing: Packing,
+    line: &mut [u8],
+) {
+    let chunk_scaled = chunk_length * scale;
+
+    for (chunk_x, maybe_chunk) in chunk_row.iter().enumerate() {
+        let row_colors = match maybe_chunk {
+            Some(chunk) => chunk.row_of_colors(row_in_chunk),
+            None => vec![Color::ZERO; chunk_length],
+        };
+
+        let base_x = chunk_x * chunk_scaled;
+
+        for (x, color) in row_colors.iter().enumerate() {
+            let color_index = color.to_index();
+
+            for dx in 0..scale {
+                let pixel_pos = base_x + x * scale + dx;
+
+                if packing == Packing::Byte {
+                    line[pixel_pos] = color_index;
+                    continue;
+                }
+
+                // Calculate byte position and bit position within byte
+                let byte_pos = pixel_pos / 2;
+                if pixel_pos & 1 == 0 {
+                    // High nibble (first 4 bits)
+                    line[byte_pos] = (line[byte_pos] & 0x0F) | (color_index << 4);
+                } else {
+                    // Low nibble (last 4 bits)
+                    line[byte_pos] = (line[byte_pos] & 0xF0) | color_index;
+                }
+            }
+        }
+    }
+}
+
+/// png encoder for 4 or 8 bit indexed images with the board palette
+fn indexed_png_encoder<W: Write>(
+    output: W,
+    width: u32,
+    height: u32,
+    compression: png::Compression,
+    palette: &Palette,
+) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(output, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(match palette.packing() {
+        Packing::Nibble => png::BitDepth::Four,
+        Packing::Byte => png::BitDepth::Eight,
+    });
+
+    encoder.set_filter(png::FilterType::NoFilter);
+    encoder.set_compression(compression);
+
+    let palette: Vec<u8> = palette
+        .colors
+        .iter()
+        .flat_map(|&Rgb(r, g, b)| [r, g, b])
+        .collect();
+    encoder.set_palette(palette);
+
+    encoder
+}
+
+/// Screenshots above this many pixels are refused
+pub const MAX_SCREENSHOT_PIXELS: u64 = 512_000_000;
+
+/// Screenshots above this many pixels are streamed instead of rendered in memory and cached
+pub const STREAM_SCREENSHOT_PIXELS: u64 = 16_000_000;
+
+/// How many pixels a screenshot of the chunks has, saturating instead of overflowing
+pub fn screenshot_pixels(x_chunks: u64, y_chunks: u64, chunk_length: usize, quality: u8) -> u64 {
+    let side = chunk_length as u64 * quality.max(1) as u64;
+    x_chunks
+        .saturating_mul(side)
+        .saturating_mul(y_chunks.saturating_mul(side))
+}
+
+/// How many pixels a screenshot of the region of the board has, see [`screenshot_pixels`]
+pub fn region_pixels(
+    board: &Board,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+    quality: u8,
+) -> u64 {
+    // on an infinite board the difference doesn't fit in an i64
+    let chunks =
+        |from: i64, to: i64| u64::try_from(to as i128 - from as i128 + 1).unwrap_or(u64::MAX);
+    screenshot_pixels(
+        chunks(top_left.x(), bottom_right.x()),
+        chunks(bottom_right.y(), top_left.y()),
+        board.chunk_length,
+        quality,
+    )
+}
+
+/// The highest quality of a screenshot of the region within [`MAX_SCREENSHOT_PIXELS`]
+pub fn best_quality(
+    board: &Board,
+    top_left: ChunkCoordinates,
+    bottom_right: ChunkCoordinates,
+) -> Option<u8> {
+    (1..=8).rev().find(|&quality| {
+        region_pixels(board, top_left, bottom_right, quality) <= MAX_SCREENSHOT_PIXELS
+    })
+}
+
+/// Sends everything written to it as [`Bytes`] in pieces of `BODY_PIECE_SIZE`
+///
+/// Used as output of [`Screenshot::stream_png`] to fill a streaming http body.
+pub struct BodyWriter {
+    sender: mpsc::Sender<Result<Bytes, std::io::Error>>,
+    buffer: Vec<u8>,
+}
+
+const BODY_PIECE_SIZE: usize = 64 * 1024;
+
+impl BodyWriter {
+    pub fn new(sender: mpsc::Sender<Result<Bytes, std::io::Error>>) -> Self {
+        Self {
+            sender,
+            buffer: Vec::with_capacity(BODY_PIECE_SIZE),
+        }
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= BODY_PIECE_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let piece = std::mem::replace(&mut self.buffer, Vec::with_capacity(BODY_PIECE_SIZE));
+        self.sender
+            .blocking_send(Ok(Bytes::from(piece)))
+            // the client is gone
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+/// Identifies a rendered screenshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScreenshotKey {
+    pub top_left: ChunkCoordinates,
+    pub bottom_right: ChunkCoordinates,
+    pub quality: u8,
+}
+
+/// Rendered screenshot pngs, stored with the version of their region.
+///
+/// An entry is only used while the region version is the same,
+/// so it is outdated as soon as a chunk inside of it changes.
+/// Oldest entries are dropped when the cache grows over [`CACHE_SIZE`] bytes.
+#[derive(Debug, Default)]
+pub struct ScreenshotCache {
+    inner: Mutex<ScreenshotCacheInner>,
+}
+
+#[derive(Debug, Default)]
+struct ScreenshotCacheInner {
+    entries: HashMap<ScreenshotKey, (u64, Bytes)>,
+    /// insertion order, for dropping the oldest
+    order: VecDeque<ScreenshotKey>,
+    bytes: usize,
+}
+
+impl ScreenshotCache {
+    pub fn get(&self, key: &ScreenshotKey, version: u64) -> Option<Bytes> {
+        let inner = self.inner.lock().ok()?;
+        match inner.entries.get(key) {
+            Some((entry_version, png)) if *entry_version == version => Some(png.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, key: ScreenshotKey, version: u64, png: Bytes) {
+        let max_bytes = CACHE_SIZE as usize;
+        if png.len() > max_bytes {
+            return;
+        }
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+
+        if let Some((_, old)) = inner.entries.remove(&key) {
+            inner.bytes -= old.len();
+            inner.order.retain(|k| k != &key);
+        }
+
+        while inner.bytes + png.len() > max_bytes {
+            let Some(oldest) = inner.order.pop_front() else {
+                break;
+            };
+            if let Some((_, old)) = inner.entries.remove(&oldest) {
+                inner.bytes -= old.len();
+            }
+        }
+
+        inner.bytes += png.len();
+        inner.order.push_back(key);
+        inner.entries.insert(key, (version, png));
+    }
+}
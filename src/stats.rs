@@ -0,0 +1,86 @@
+use std::{collections::VecDeque, time::Instant};
+
+use serde::Serialize;
+
+/// How far back the [`PixelCounter`] remembers, in seconds
+const COUNTER_WINDOW_SECS: u64 = 15 * 60;
+
+/// Counts pixel updates in buckets of one second, for the last [`COUNTER_WINDOW_SECS`]
+#[derive(Debug)]
+pub struct PixelCounter {
+    started: Instant,
+    /// (second since started, pixels in that second)
+    buckets: VecDeque<(u64, u64)>,
+    total: u64,
+}
+
+impl Default for PixelCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PixelCounter {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            buckets: VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    pub fn record(&mut self, pixels: u64) {
+        let now = self.now();
+        self.total += pixels;
+
+        match self.buckets.back_mut() {
+            Some((second, count)) if *second == now => *count += pixels,
+            _ => self.buckets.push_back((now, pixels)),
+        }
+
+        while let Some((second, _)) = self.buckets.front() {
+            if now - second < COUNTER_WINDOW_SECS {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    /// pixels counted in the last `secs` seconds
+    pub fn in_last(&self, secs: u64) -> u64 {
+        let now = self.now();
+        self.buckets
+            .iter()
+            .rev()
+            .take_while(|(second, _)| now - second < secs)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn throughput(&self) -> Throughput {
+        Throughput {
+            last_minute: self.in_last(60),
+            last_5_minutes: self.in_last(5 * 60),
+            last_15_minutes: self.in_last(15 * 60),
+            total: self.total,
+        }
+    }
+}
+
+/// Pixel updates over recent windows
+#[derive(Debug, Clone, Serialize)]
+pub struct Throughput {
+    pub last_minute: u64,
+    pub last_5_minutes: u64,
+    pub last_15_minutes: u64,
+    /// since the counter was started
+    pub total: u64,
+}
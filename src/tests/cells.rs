@@ -0,0 +1,100 @@
+use paintplayground::types::*;
+
+fn cells(cells: &[(usize, u8)]) -> Vec<PackedCell> {
+    cells
+        .iter()
+        .map(|&(index, color)| PackedCell::new(index, color).unwrap())
+        .collect()
+}
+
+#[test]
+fn encodings_round_trip() {
+    let board = Board::main();
+    let updates = cells(&[
+        (0, 3),
+        (1, 3),
+        (2, 3),
+        (7, 15),
+        (board.chunk_size() - 1, 1),
+        (5, 3),
+    ]);
+
+    for encoding in [CellEncoding::Wide, CellEncoding::Compact] {
+        let mut buffer = Vec::new();
+        encoding.encode(&updates, Packing::Nibble, &mut buffer);
+        assert_eq!(encoding.decode(&buffer, &board), updates, "{:?}", encoding);
+    }
+}
+
+#[test]
+fn compact_cells_are_smaller() {
+    let updates = cells(&[(4_000, 2), (9_999, 15)]);
+
+    let mut buffer = Vec::new();
+    CellEncoding::Compact.encode(&updates, Packing::Nibble, &mut buffer);
+    // 14 bit index, 4 bit color and the run flag
+    assert_eq!(buffer.len(), 6);
+
+    // a whole row is a single record
+    let row = (100..200).map(|index| (index, 9)).collect::<Vec<_>>();
+    let mut buffer = Vec::new();
+    CellEncoding::Compact.encode(&cells(&row), Packing::Nibble, &mut buffer);
+    assert_eq!(buffer.len(), 3);
+}
+
+fn varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[test]
+fn compact_frames_from_clients_are_checked() {
+    let board = Board::main();
+    let last = board.chunk_size() - 1;
+    let record = |index: usize, color: u64| ((index as u64) << Packing::Nibble.bits() | color) << 1;
+
+    let mut buffer = Vec::new();
+    // a run of 10 past the end of the chunk is cut off
+    varint(&mut buffer, record(last - 1, 4) | 1);
+    varint(&mut buffer, 10);
+    // an index outside of the chunk is left out
+    varint(&mut buffer, record(board.chunk_size(), 4));
+    varint(&mut buffer, record(1, 1));
+    assert_eq!(
+        CellEncoding::Compact.decode(&buffer, &board),
+        cells(&[(last - 1, 4), (last, 4), (1, 1)])
+    );
+
+    // a cut off varint ends the frame
+    buffer.push(0x80);
+    assert_eq!(CellEncoding::Compact.decode(&buffer, &board).len(), 3);
+    // as does one longer than a u64
+    buffer.pop();
+    buffer.extend_from_slice(&[0xFF; 11]);
+    buffer.push(0);
+    assert_eq!(CellEncoding::Compact.decode(&buffer, &board).len(), 3);
+}
+
+#[test]
+fn compact_frames_paint_at_most_a_chunk() {
+    let board = Board::main();
+    let record = |index: usize, color: u64| ((index as u64) << Packing::Nibble.bits() | color) << 1;
+
+    // every record is a run over the whole chunk
+    let mut buffer = Vec::new();
+    for _ in 0..1_000 {
+        varint(&mut buffer, record(0, 2) | 1);
+        varint(&mut buffer, u64::MAX);
+    }
+    assert!(buffer.len() < 20_000);
+
+    let decoded = CellEncoding::Compact.decode(&buffer, &board);
+    assert_eq!(decoded.len(), board.chunk_size());
+    assert_eq!(
+        decoded.last(),
+        Some(&PackedCell::new(board.chunk_size() - 1, 2).unwrap())
+    );
+}
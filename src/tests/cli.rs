@@ -0,0 +1,154 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use clap::Parser;
+use paintplayground::{
+    chunk_db::StorageBackend,
+    types::{Board, Bounds, CompressionPolicy, CompressionType, DEFAULT_CHUNK_LENGTH, Palette},
+};
+
+use crate::cli::{Cli, Command};
+use crate::config::Config;
+
+#[test]
+fn no_subcommand_serves() {
+    let cli = Cli::try_parse_from(["server", "--port", "8080", "--bind", "127.0.0.1"]).unwrap();
+
+    assert!(cli.command.is_none());
+
+    let mut config = Config::default();
+    cli.serve.apply(&mut config);
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.server.bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+    // not given, so the config value stays
+    assert_eq!(config.server.backend, Config::default().server.backend);
+}
+
+#[test]
+fn region_flags_take_negative_numbers() {
+    let cli = Cli::try_parse_from([
+        "server",
+        "export",
+        "--to",
+        "backup",
+        "--x",
+        "-2",
+        "--y",
+        "3",
+        "--x2",
+        "4",
+        "--y2",
+        "-1",
+        "--backend",
+        "file",
+    ])
+    .unwrap();
+
+    let Some(Command::Export(args)) = cli.command else {
+        panic!("expected export, got {:?}", cli.command);
+    };
+    assert_eq!(args.backend, StorageBackend::File);
+
+    let (top_left, bottom_right) = args.region.corners(&Board::main()).unwrap();
+    assert_eq!((top_left.x(), top_left.y()), (-2, 3));
+    assert_eq!((bottom_right.x(), bottom_right.y()), (4, -1));
+}
+
+#[test]
+fn region_has_to_start_top_left() {
+    let cli = Cli::try_parse_from(["server", "stats", "--x", "2", "--x2", "1"]).unwrap();
+
+    let Some(Command::Stats(args)) = cli.command else {
+        panic!("expected stats, got {:?}", cli.command);
+    };
+    assert!(args.region.corners(&Board::main()).is_err());
+}
+
+#[test]
+fn region_is_checked_against_the_grown_board() {
+    let cli = Cli::try_parse_from(["server", "stats", "--x", "-9", "--y", "9"]).unwrap();
+    let Some(Command::Stats(args)) = cli.command else {
+        panic!("expected stats, got {:?}", cli.command);
+    };
+
+    let board = Board::new(
+        "growing".to_string(),
+        Bounds::square(2),
+        Palette::default(),
+        DEFAULT_CHUNK_LENGTH,
+        String::new(),
+    );
+    assert!(args.region.corners(&board).is_err());
+
+    board.grow(Bounds::square(9)).unwrap();
+    let (top_left, bottom_right) = args.region.corners(&board).unwrap();
+    assert_eq!((top_left.x(), top_left.y()), (-9, 9));
+    assert_eq!((bottom_right.x(), bottom_right.y()), (9, -9));
+}
+
+#[test]
+fn plot_quality_is_limited() {
+    assert!(Cli::try_parse_from(["server", "plot", "--q", "9"]).is_err());
+    assert!(Cli::try_parse_from(["server", "plot", "--q", "0"]).is_err());
+}
+
+#[test]
+fn migrate_parses_backends_and_compression() {
+    let cli = Cli::try_parse_from([
+        "server",
+        "migrate",
+        "--from",
+        "file",
+        "--to",
+        "r2",
+        "--compression",
+        "lz4",
+        "--dry-run",
+    ])
+    .unwrap();
+
+    let Some(Command::Migrate(args)) = cli.command else {
+        panic!("expected migrate, got {:?}", cli.command);
+    };
+    assert_eq!(args.from, StorageBackend::File);
+    assert_eq!(args.to, StorageBackend::R2);
+    assert_eq!(args.compression, Some(CompressionType::Lz4.into()));
+
+    assert_eq!("zstd-dict:3".parse(), Ok(CompressionType::ZstdDict(3)));
+    assert!("zstd-dict:300".parse::<CompressionType>().is_err());
+
+    assert_eq!(
+        "smallest:lz4, zstd:19".parse(),
+        Ok(CompressionPolicy::Smallest(vec![
+            CompressionType::Lz4,
+            CompressionType::Zstd(19)
+        ]))
+    );
+    assert!("zstd:99".parse::<CompressionPolicy>().is_err());
+    assert!("smallest:lz4,gzip".parse::<CompressionPolicy>().is_err());
+    assert_eq!(args.concurrency, 32);
+    assert!(args.dry_run);
+
+    assert!(
+        Cli::try_parse_from([
+            "server",
+            "migrate",
+            "--from",
+            "file",
+            "--to",
+            "r2",
+            "--compression",
+            "gzip"
+        ])
+        .is_err()
+    );
+}
+
+#[test]
+fn board_defaults_to_the_main_board() {
+    let cli = Cli::try_parse_from(["server", "verify", "--backend", "file"]).unwrap();
+    assert_eq!(cli.board, paintplayground::types::MAIN_BOARD);
+
+    let cli =
+        Cli::try_parse_from(["server", "verify", "--backend", "file", "--board", "event"]).unwrap();
+    assert_eq!(cli.board, "event");
+}
@@ -0,0 +1,243 @@
+use paintplayground::{
+    chunk_db::StorageBackend,
+    types::{Board, Bounds, CompressionPolicy, MAIN_BOARD, Rgb},
+};
+
+use crate::config::{Config, ConfigError};
+
+#[test]
+fn defaults_are_valid() {
+    let config = Config::default();
+
+    assert!(config.validate().is_ok());
+    assert_eq!(config.board.max_live_chunks, 100);
+    assert_eq!(config.chunk.idle_timeout_secs, 300);
+
+    let main = Board::main();
+    let boards = config.boards();
+    assert_eq!(boards[0].bounds(), main.bounds());
+    assert_eq!(boards[0].palette, main.palette);
+    assert_eq!(boards[0].chunk_length, main.chunk_length);
+}
+
+#[test]
+fn partial_file_keeps_defaults() {
+    let config: Config = toml::from_str(
+        r#"
+        chunks_in_direction = 3
+
+        [server]
+        port = 8080
+        backend = "file"
+
+        [chunk]
+        idle_timeout_secs = 10
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.chunks_in_direction, 3);
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.server.backend, StorageBackend::File);
+    assert_eq!(config.chunk.idle_timeout_secs, 10);
+    assert_eq!(config.chunk.update_channel_size, 1000);
+    assert_eq!(config.board.max_live_chunks, 100);
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    assert!(toml::from_str::<Config>("[board]\nmax_chunks = 5").is_err());
+}
+
+#[test]
+fn storage_compression_is_parsed() {
+    let config: Config = toml::from_str("[storage]\ncompression = \"smallest\"").unwrap();
+    assert_eq!(
+        config.storage.compression,
+        CompressionPolicy::Smallest(CompressionPolicy::default_candidates())
+    );
+    assert!(
+        config
+            .boards()
+            .iter()
+            .all(|board| board.compression == config.storage.compression)
+    );
+
+    assert!(toml::from_str::<Config>("[storage]\ncompression = \"zip\"").is_err());
+}
+
+#[test]
+fn rle_is_invalid_for_byte_palettes() {
+    let colors = vec!["\"#000000\""; 17].join(", ");
+    let config = |compression: &str| {
+        let config: Config = toml::from_str(&format!(
+            "[storage]\ncompression = \"{}\"\n[[boards]]\nname = \"large\"\npalette = {{ name = \"large\", colors = [{}] }}",
+            compression, colors
+        ))
+        .unwrap();
+        config.validate()
+    };
+
+    assert!(config("zstd").is_ok());
+    assert!(config("smallest:lz4,zstd:19").is_ok());
+    for compression in ["rle", "rle-zstd", "smallest"] {
+        let err = config(compression).unwrap_err();
+        assert!(err.to_string().contains("\"large\""));
+    }
+}
+
+#[test]
+fn zero_sized_channels_are_invalid() {
+    let mut config = Config::default();
+    config.chunk.update_channel_size = 0;
+
+    let err = config.validate().unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+    assert!(err.to_string().contains("chunk.update_channel_size"));
+}
+
+#[test]
+fn odd_chunk_length_is_invalid() {
+    let config: Config = toml::from_str("chunk_length = 50").unwrap();
+    assert_eq!(config.chunk_length, 50);
+    assert!(config.validate().is_ok());
+
+    for chunk_length in [0, 51, 2000] {
+        let config = Config {
+            chunk_length,
+            ..Default::default()
+        };
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("chunk_length"));
+    }
+
+    let config: Config = toml::from_str("[[boards]]\nname = \"event\"\nchunk_length = 7").unwrap();
+    let err = config.validate().unwrap_err();
+    assert!(err.to_string().contains("\"event\""));
+}
+
+#[test]
+fn negative_board_is_invalid() {
+    let config = Config {
+        chunks_in_direction: -1,
+        ..Default::default()
+    };
+
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn palette_is_parsed() {
+    let config: Config = toml::from_str(
+        r##"
+        [palette]
+        name = "mono"
+        colors = ["#ffffff", "#000000"]
+        "##,
+    )
+    .unwrap();
+    assert_eq!(config.palette.name, "mono");
+    assert_eq!(config.palette.colors, [Rgb(255, 255, 255), Rgb(0, 0, 0)]);
+    assert!(config.validate().is_ok());
+    assert_eq!(config.boards()[0].palette, config.palette);
+
+    assert!(toml::from_str::<Config>("[palette]\nname = \"bad\"\ncolors = [\"white\"]").is_err());
+
+    let config: Config = toml::from_str("[palette]\nname = \"empty\"\ncolors = []").unwrap();
+    let err = config.validate().unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)));
+}
+
+#[test]
+fn named_boards_are_parsed() {
+    let config: Config = toml::from_str(
+        r##"
+        chunks_in_direction = 20
+
+        [[boards]]
+        name = "event"
+        chunks_in_direction = 2
+
+        [[boards]]
+        name = "mono"
+        chunk_length = 32
+        storage_prefix = "boards/mono/"
+        palette = { name = "mono", colors = ["#ffffff", "#000000"] }
+        "##,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+
+    let boards = config.boards();
+    let names: Vec<&str> = boards.iter().map(|board| board.name.as_str()).collect();
+    assert_eq!(names, [MAIN_BOARD, "event", "mono"]);
+
+    assert_eq!(boards[0].storage_prefix, "");
+    assert_eq!(boards[1].storage_prefix, "event/");
+    assert_eq!(boards[1].bounds(), Bounds::square(2));
+    assert_eq!(boards[2].storage_prefix, "boards/mono/");
+    assert_eq!(boards[2].chunk_length, 32);
+    assert_eq!(boards[2].bounds(), Bounds::square(20));
+    assert_eq!(boards[1].palette, config.palette);
+    assert_eq!(boards[2].palette.colors.len(), 2);
+}
+
+#[test]
+fn conflicting_boards_are_invalid() {
+    let invalid = |boards: &str| {
+        let config: Config = toml::from_str(boards).unwrap();
+        config.validate().is_err()
+    };
+
+    // names end up in urls
+    assert!(invalid("[[boards]]\nname = \"a/b\""));
+    assert!(invalid("[[boards]]\nname = \"\""));
+    // the main board is already there
+    assert!(invalid("[[boards]]\nname = \"main\""));
+    assert!(invalid(
+        "[[boards]]\nname = \"event\"\n[[boards]]\nname = \"event\"\nstorage_prefix = \"other/\""
+    ));
+    // would share the chunks of the main board
+    assert!(invalid(
+        "[[boards]]\nname = \"event\"\nstorage_prefix = \"\""
+    ));
+    assert!(invalid(
+        "[[boards]]\nname = \"event\"\nstorage_prefix = \"chunks/\""
+    ));
+    assert!(invalid(
+        "[[boards]]\nname = \"event\"\nchunks_in_direction = -1"
+    ));
+}
+
+#[test]
+fn bounds_are_parsed() {
+    let config: Config = toml::from_str(
+        r#"
+        chunks_in_direction = 5
+        bounds = { min_x = 0, max_x = 99, min_y = -10, max_y = 0 }
+
+        [[boards]]
+        name = "endless"
+        bounds = "infinite"
+
+        [[boards]]
+        name = "small"
+        chunks_in_direction = 1
+        "#,
+    )
+    .unwrap();
+    assert!(config.validate().is_ok());
+
+    let boards = config.boards();
+    assert_eq!(boards[0].bounds(), Bounds::new(0, 99, -10, 0).unwrap());
+    assert!(boards[1].bounds().is_infinite());
+    assert_eq!(boards[2].bounds(), Bounds::square(1));
+    // only the configured board is smaller than the default
+    assert!(boards[0].coordinates(-1, 0).is_err());
+    assert!(Board::main().coordinates(-1, 0).is_ok());
+
+    assert!(
+        toml::from_str::<Config>("bounds = { min_x = 1, max_x = 0, min_y = 0, max_y = 0 }")
+            .is_err()
+    );
+}
@@ -0,0 +1,12 @@
+use paintplayground::chunk_db::SimpleToFileSaver;
+
+use crate::board_manager::BoardManager;
+use crate::config::Config;
+
+#[tokio::test]
+async fn board_manager_answers_health_checks() {
+    let communicator = BoardManager::start(SimpleToFileSaver::new(), &Config::default());
+
+    assert!(communicator.ping().await);
+    assert!(communicator.probe_storage().await.is_ok());
+}
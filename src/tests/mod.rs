@@ -1 +1,10 @@
+mod cells;
+mod cli;
+mod config;
+mod health;
+mod router;
+mod screenshot;
+mod stats;
+mod storage;
 mod test;
+mod tiles;
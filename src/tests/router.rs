@@ -0,0 +1,222 @@
+use std::net::SocketAddr;
+
+use paintplayground::{
+    chunk_db::{ChunkLoaderSaver, SimpleToFileSaver},
+    types::*,
+};
+
+use crate::board_manager::BoardManager;
+use crate::config::Config;
+use crate::{AppState, BoardHandle, router};
+
+/// Serve [`router::all_routes`] on a free port
+async fn serve(boards: Vec<BoardHandle>) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let app = router::all_routes(AppState::new(boards, None));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    address
+}
+
+fn handle(saver: SimpleToFileSaver) -> BoardHandle {
+    let board = saver.board().clone();
+    BoardHandle::new(board, BoardManager::start(saver, &Config::default()))
+}
+
+/// The packed pixels of a chunk request
+async fn chunk_data(url: String) -> Vec<u8> {
+    let response = reqwest::get(url).await.unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    response.bytes().await.unwrap().to_vec()
+}
+
+/// A chunk request with one extra header
+async fn get_with(url: &str, name: reqwest::header::HeaderName, value: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(url)
+        .header(name, value)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn boards_are_routed_by_name() {
+    let prefix = std::env::temp_dir().join("paintplayground-router-");
+    let named = Arc::new(Board::new(
+        "named".to_string(),
+        Bounds::square(8),
+        Palette::default(),
+        DEFAULT_CHUNK_LENGTH,
+        prefix.to_string_lossy().into_owned(),
+    ));
+    let main = SimpleToFileSaver::new();
+    let other = SimpleToFileSaver::with_board(named.clone());
+
+    let coordinates = ChunkCoordinates::new(-7, 6).unwrap();
+    let mut main_chunk = Chunk::new();
+    main_chunk.set_pixel(0, Color::TWO);
+    main.save_chunk(main_chunk.clone(), coordinates)
+        .await
+        .unwrap();
+    let mut named_chunk = Chunk::new();
+    named_chunk.set_pixel(0, Color::THREE);
+    other
+        .save_chunk(named_chunk.clone(), coordinates)
+        .await
+        .unwrap();
+
+    let address = serve(vec![handle(main), handle(other)]).await;
+
+    assert_eq!(
+        chunk_data(format!("http://{address}/chunk/-7/6")).await,
+        main_chunk.data()
+    );
+    assert_eq!(
+        chunk_data(format!("http://{address}/b/named/chunk/-7/6")).await,
+        named_chunk.data()
+    );
+
+    let response = reqwest::get(format!("http://{address}/b/missing/chunk/-7/6"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    std::fs::remove_file(format!("canvas/{}", coordinates.object_name())).unwrap();
+    std::fs::remove_file(format!(
+        "{}canvas/{}",
+        named.storage_prefix,
+        coordinates.object_name()
+    ))
+    .unwrap();
+}
+
+#[tokio::test]
+async fn chunk_is_served_in_every_format() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(-7, 5).unwrap();
+    let mut chunk = Chunk::new();
+    chunk.set_pixel(0, Color::TWO);
+    saver.save_chunk(chunk.clone(), coordinates).await.unwrap();
+
+    let address = serve(vec![handle(saver)]).await;
+    let url = format!("http://{address}/chunk/-7/5");
+
+    assert_eq!(chunk_data(url.clone()).await, chunk.data());
+
+    let compressed = chunk_data(format!("{url}?format=compressed")).await;
+    assert_eq!(Chunk::decode(&compressed).unwrap().data(), chunk.data());
+
+    let response = reqwest::get(format!("{url}?format=png&q=2")).await.unwrap();
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let png = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+    assert_eq!(
+        (png.width(), png.height()),
+        (
+            2 * DEFAULT_CHUNK_LENGTH as u32,
+            2 * DEFAULT_CHUNK_LENGTH as u32
+        )
+    );
+
+    let json = chunk_data(format!("{url}?format=json")).await;
+    let colors: Vec<&str> = std::str::from_utf8(&json)
+        .unwrap()
+        .trim_matches(['[', ']'])
+        .split(',')
+        .collect();
+    assert_eq!(colors.len(), Board::main().chunk_size());
+    assert_eq!(colors[..2], ["2", "0"]);
+
+    std::fs::remove_file(format!("canvas/{}", coordinates.object_name())).unwrap();
+}
+
+#[tokio::test]
+async fn unchanged_chunk_is_not_sent_again() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(-7, 4).unwrap();
+    saver
+        .save_chunk(Chunk::filled(Color::FIVE), coordinates)
+        .await
+        .unwrap();
+
+    let address = serve(vec![handle(saver)]).await;
+    let url = format!("http://{address}/chunk/-7/4");
+
+    let response = reqwest::get(&url).await.unwrap();
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let last_modified = response.headers()["last-modified"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let response = get_with(&url, reqwest::header::IF_NONE_MATCH, &etag).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+    let response = get_with(&url, reqwest::header::IF_NONE_MATCH, "\"other\"").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let response = get_with(&url, reqwest::header::IF_MODIFIED_SINCE, &last_modified).await;
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_MODIFIED);
+    let before = "Mon, 01 Jan 2001 00:00:00 GMT";
+    let response = get_with(&url, reqwest::header::IF_MODIFIED_SINCE, before).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // every format has its own ETag
+    let response = get_with(
+        &format!("{url}?format=json"),
+        reqwest::header::IF_NONE_MATCH,
+        &etag,
+    )
+    .await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    std::fs::remove_file(format!("canvas/{}", coordinates.object_name())).unwrap();
+}
+
+#[tokio::test]
+async fn painted_chunk_is_served_live_and_stored() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(-7, 3).unwrap();
+    let stored = Chunk::filled(Color::FOUR);
+    saver.save_chunk(stored.clone(), coordinates).await.unwrap();
+
+    let board = handle(saver);
+    let communicator = board.board_communicator.clone();
+    let address = serve(vec![board]).await;
+    let url = format!("http://{address}/chunk/-7/3");
+    let etag = reqwest::get(&url).await.unwrap().headers()["etag"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let mut changes = communicator.chunk_changes();
+    let handler = communicator.get_handler(coordinates).await.unwrap();
+    handler
+        .update_tx
+        .send(vec![PackedCell::new(0, 3).unwrap()])
+        .await
+        .unwrap();
+    changes.changed().await.unwrap();
+
+    let live = chunk_data(url.clone()).await;
+    assert_eq!(
+        Chunk::try_from(live.clone()).unwrap().pixel(0).u8(),
+        Color::THREE.u8()
+    );
+    assert_ne!(live, stored.data());
+    // the ChunkManager saves a change before it answers the next request
+    assert_eq!(chunk_data(format!("{url}?source=stored")).await, live);
+
+    // the painted chunk has a new ETag
+    for source in ["live", "stored"] {
+        let response = get_with(
+            &format!("{url}?source={source}"),
+            reqwest::header::IF_NONE_MATCH,
+            &etag,
+        )
+        .await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    drop(handler);
+    std::fs::remove_file(format!("canvas/{}", coordinates.object_name())).unwrap();
+}
@@ -0,0 +1,168 @@
+use axum::body::Bytes;
+use paintplayground::{chunk_db::SimpleToFileSaver, types::*};
+
+use crate::config::Config;
+use crate::{
+    board_manager::{BoardManager, ChunkRequest},
+    screenshot::{self, Screenshot, ScreenshotCache, ScreenshotKey},
+};
+
+fn decode_png(png: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+    let mut reader = png::Decoder::new(png).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    (info, pixels)
+}
+
+#[test]
+fn streamed_png_matches_in_memory_png() {
+    let board = Board::main();
+    let mut chunk = board.blank_chunk();
+    chunk.set_pixel(0, Color::THREE);
+    chunk.set_pixel(board.chunk_size() - 1, Color::FIFTEEN);
+    let rows = vec![vec![Some(chunk.clone()), None], vec![None, Some(chunk)]];
+
+    let in_memory = Screenshot::from_chunks(rows.clone(), &board).create_png(2);
+
+    let (rows_tx, rows_rx) = mpsc::channel(rows.len());
+    for row in rows {
+        rows_tx.try_send(row).unwrap();
+    }
+    let mut streamed = Vec::new();
+    Screenshot::stream_png(rows_rx, 2, 2, 2, &board, &mut streamed).unwrap();
+
+    let (info, pixels) = decode_png(&in_memory);
+    let (streamed_info, streamed_pixels) = decode_png(&streamed);
+    assert_eq!((info.width, info.height), (400, 400));
+    assert_eq!(
+        (info.width, info.height),
+        (streamed_info.width, streamed_info.height)
+    );
+    assert_eq!(pixels, streamed_pixels);
+}
+
+#[test]
+fn screenshot_pixels_saturate() {
+    assert_eq!(screenshot::screenshot_pixels(2, 3, 100, 1), 60_000);
+    assert_eq!(screenshot::screenshot_pixels(1, 1, 100, 0), 10_000);
+    assert_eq!(screenshot::screenshot_pixels(1, 1, 20, 2), 1_600);
+    assert_eq!(screenshot::screenshot_pixels(u64::MAX, 2, 100, 8), u64::MAX);
+}
+
+#[test]
+fn screenshot_cache_checks_the_version() {
+    let cache = ScreenshotCache::default();
+    let key = ScreenshotKey {
+        top_left: ChunkCoordinates::new(-1, 1).unwrap(),
+        bottom_right: ChunkCoordinates::new(1, -1).unwrap(),
+        quality: 2,
+    };
+
+    cache.insert(key, 7, Bytes::from_static(b"png"));
+
+    assert_eq!(cache.get(&key, 7), Some(Bytes::from_static(b"png")));
+    assert_eq!(cache.get(&key, 8), None);
+    assert_eq!(cache.get(&ScreenshotKey { quality: 3, ..key }, 7), None);
+}
+
+#[tokio::test]
+async fn region_version_changes_with_its_chunks() {
+    let communicator = BoardManager::start(SimpleToFileSaver::new(), &Config::default());
+
+    let changed = ChunkCoordinates::new(-3, 3).unwrap();
+    let region = (
+        ChunkCoordinates::new(-4, 4).unwrap(),
+        ChunkCoordinates::new(-2, 2).unwrap(),
+    );
+    let other_region = (
+        ChunkCoordinates::new(2, 4).unwrap(),
+        ChunkCoordinates::new(4, 2).unwrap(),
+    );
+
+    let before = communicator.region_version(region.0, region.1);
+    let other_before = communicator.region_version(other_region.0, other_region.1);
+
+    let mut changes = communicator.chunk_changes();
+    let handler = communicator.get_handler(changed).await.unwrap();
+    handler
+        .update_tx
+        .send(vec![PackedCell::new(5, 9).unwrap()])
+        .await
+        .unwrap();
+
+    // the ChunkManager applies the update after its buffer interval
+    changes.changed().await.unwrap();
+
+    assert_ne!(before, communicator.region_version(region.0, region.1));
+    assert_eq!(
+        other_before,
+        communicator.region_version(other_region.0, other_region.1)
+    );
+
+    // the ChunkManager saves a change before it answers the next request
+    communicator.get_chunk(changed, ChunkRequest::Live).await;
+    drop(handler);
+    std::fs::remove_file(format!("canvas/{}", changed.object_name())).unwrap();
+}
+
+#[tokio::test]
+async fn huge_regions_are_refused() {
+    let board = Arc::new(Board::new(
+        "huge".to_string(),
+        Bounds::INFINITE,
+        Palette::default(),
+        DEFAULT_CHUNK_LENGTH,
+        String::new(),
+    ));
+    let saver = SimpleToFileSaver::with_board(board.clone());
+
+    // the width of the board doesn't fit in an i64
+    let top_left = board.coordinates(i64::MIN, i64::MAX).unwrap();
+    let bottom_right = board.coordinates(i64::MAX, i64::MIN).unwrap();
+    let err = Screenshot::from_coordinates(&saver, top_left, bottom_right)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.0, u128::MAX);
+
+    // 1000 x 101 chunks
+    let top_left = board.coordinates(0, 0).unwrap();
+    let bottom_right = board.coordinates(999, -100).unwrap();
+    assert!(
+        Screenshot::from_coordinates(&saver, top_left, bottom_right)
+            .await
+            .is_err()
+    );
+}
+
+#[test]
+fn best_quality_fits_in_the_pixel_limit() {
+    let board = Board::new(
+        "wide".to_string(),
+        Bounds::INFINITE,
+        Palette::default(),
+        DEFAULT_CHUNK_LENGTH,
+        String::new(),
+    );
+
+    let chunk = board.coordinates(0, 0).unwrap();
+    assert_eq!(screenshot::best_quality(&board, chunk, chunk), Some(8));
+
+    // 1000 x 1000 chunks
+    let top_left = board.coordinates(0, 0).unwrap();
+    let bottom_right = board.coordinates(999, -999).unwrap();
+    let quality = screenshot::best_quality(&board, top_left, bottom_right);
+    assert!(quality.is_none_or(|quality| {
+        screenshot::region_pixels(&board, top_left, bottom_right, quality)
+            <= screenshot::MAX_SCREENSHOT_PIXELS
+            && screenshot::region_pixels(&board, top_left, bottom_right, quality + 1)
+                > screenshot::MAX_SCREENSHOT_PIXELS
+    }));
+
+    let top_left = board.coordinates(i64::MIN, i64::MAX).unwrap();
+    let bottom_right = board.coordinates(i64::MAX, i64::MIN).unwrap();
+    assert_eq!(
+        screenshot::best_quality(&board, top_left, bottom_right),
+        None
+    );
+}
@@ -0,0 +1,39 @@
+use paintplayground::{chunk_db::SimpleToFileSaver, types::*};
+
+use crate::config::Config;
+use crate::{board_manager::BoardManager, stats::PixelCounter};
+
+#[test]
+fn pixel_counter_windows() {
+    let mut counter = PixelCounter::new();
+    counter.record(3);
+    counter.record(4);
+
+    assert_eq!(counter.in_last(60), 7);
+    assert_eq!(counter.total(), 7);
+    assert_eq!(counter.throughput().last_15_minutes, 7);
+}
+
+#[tokio::test]
+async fn stats_include_live_chunks() {
+    let communicator = BoardManager::start(SimpleToFileSaver::new(), &Config::default());
+
+    let coordinates = ChunkCoordinates::new(1, -1).unwrap();
+    let handler = communicator.get_handler(coordinates).await.unwrap();
+    handler
+        .update_tx
+        .send(vec![PackedCell::new(0, 3).unwrap()])
+        .await
+        .unwrap();
+
+    // the update is counted as soon as the ChunkManager receives it
+    let _ = handler.fetch_chunk().await;
+
+    let stats = communicator.get_stats().await.unwrap();
+    assert_eq!(stats.live_chunks, 1);
+    assert_eq!((stats.chunks[0].x, stats.chunks[0].y), (1, -1));
+    assert_eq!(stats.chunks[0].pixels_total, 1);
+    assert_eq!(stats.chunks[0].connections, 1);
+    assert_eq!(stats.throughput.total, 1);
+    assert_eq!(stats.most_active.len(), 1);
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use paintplayground::{
+    chunk_db::{ChunkLoaderSaver, SimpleToFileSaver},
+    types::*,
+};
+
+use crate::board_manager::{BoardManager, ChunkRequest};
+use crate::chunk_manager::ChunkBroadcast;
+use crate::config::Config;
+
+#[tokio::test]
+async fn undecodable_chunk_is_not_overwritten() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(6, -6).unwrap();
+    let path = format!("canvas/{}", coordinates.object_name());
+    let corrupt = vec![9, 1, 2, 3];
+    std::fs::write(&path, &corrupt).unwrap();
+
+    let mut config = Config::default();
+    config.chunk.clear_buffer_interval_ms = 10;
+    let communicator = BoardManager::start(saver, &config);
+
+    let handler = communicator.get_handler(coordinates).await.unwrap();
+    handler
+        .update_tx
+        .send(vec![PackedCell::new(0, 3).unwrap()])
+        .await
+        .unwrap();
+
+    // a few buffer intervals, the update would have been saved by now
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let chunk = handler.fetch_chunk().await;
+    assert_eq!(chunk.pixel(0).u8(), Color::ZERO.u8());
+    assert_eq!(std::fs::read(&path).unwrap(), corrupt);
+    // the websockets tell their clients
+    assert!(*handler.read_only_rx.borrow());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn reset_live_chunk_broadcasts_blank_chunk() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(7, -7).unwrap();
+    let path = format!("canvas/{}", coordinates.object_name());
+    let mut chunk = Chunk::new();
+    chunk.set_pixel(0, Color::THREE);
+    saver.save_chunk(chunk, coordinates).await.unwrap();
+
+    let communicator = BoardManager::start(saver, &Config::default());
+    let mut handler = communicator.get_handler(coordinates).await.unwrap();
+    assert_eq!(handler.fetch_chunk().await.pixel(0).u8(), Color::THREE.u8());
+
+    communicator.reset_chunk(coordinates).await.unwrap();
+
+    match handler.broadcast_rx.recv().await.unwrap() {
+        ChunkBroadcast::EntireChunk(chunk) => assert_eq!(chunk.pixel(0).u8(), Color::ZERO.u8()),
+        ChunkBroadcast::Updates(_) => panic!("expected the entire chunk"),
+    }
+    assert_eq!(handler.fetch_chunk().await.pixel(0).u8(), Color::ZERO.u8());
+    assert!(!std::path::Path::new(&path).exists());
+}
+
+#[tokio::test]
+async fn reset_stored_chunk_deletes_it() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(7, -8).unwrap();
+    let path = format!("canvas/{}", coordinates.object_name());
+    saver
+        .save_chunk(Chunk::filled(Color::TWO), coordinates)
+        .await
+        .unwrap();
+    assert!(std::path::Path::new(&path).exists());
+
+    let communicator = BoardManager::start(saver, &Config::default());
+    communicator.reset_chunk(coordinates).await.unwrap();
+    assert!(!std::path::Path::new(&path).exists());
+
+    // resetting a chunk that isn't stored is fine
+    communicator.reset_chunk(coordinates).await.unwrap();
+}
+
+#[tokio::test]
+async fn live_chunk_knows_its_last_change() {
+    let saver = SimpleToFileSaver::new();
+    let coordinates = ChunkCoordinates::new(5, -9).unwrap();
+    let path = format!("canvas/{}", coordinates.object_name());
+    saver
+        .save_chunk(Chunk::filled(Color::FOUR), coordinates)
+        .await
+        .unwrap();
+
+    let mut config = Config::default();
+    config.chunk.clear_buffer_interval_ms = 10;
+    let communicator = BoardManager::start(saver, &config);
+    let stored_at = communicator
+        .chunk_metadata(coordinates)
+        .await
+        .unwrap()
+        .and_then(|metadata| metadata.last_modified);
+    assert!(stored_at.is_some());
+    assert!(communicator.last_change(coordinates).is_none());
+
+    let mut changes = communicator.chunk_changes();
+    let handler = communicator.get_handler(coordinates).await.unwrap();
+    handler
+        .update_tx
+        .send(vec![PackedCell::new(0, 3).unwrap()])
+        .await
+        .unwrap();
+    // the BoardManager hears of the change after the chunk applied it
+    changes.changed().await.unwrap();
+
+    let live = communicator
+        .get_chunk(coordinates, ChunkRequest::Live)
+        .await
+        .unwrap();
+    assert_eq!(live.pixel(0).u8(), Color::THREE.u8());
+    assert!(communicator.last_change(coordinates) >= stored_at);
+
+    std::fs::remove_file(&path).unwrap();
+}
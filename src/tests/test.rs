@@ -1,6 +1,6 @@
 use std::{io::Write, os::unix::fs::MetadataExt};
 
-use paintplayground::types::{CHUNK_BYTE_SIZE, CHUNK_SIZE, Chunk, PackedCell};
+use paintplayground::types::{Board, Chunk, PackedCell};
 
 const CHUNKS_IN_DIRECTION: usize = 20000;
 const CHUNKS_IN_MAP: usize = CHUNKS_IN_DIRECTION * CHUNKS_IN_DIRECTION;
@@ -13,12 +13,15 @@ const CHUNKS_IN_MAP: usize = CHUNKS_IN_DIRECTION * CHUNKS_IN_DIRECTION;
 
 #[test]
 fn calculate_stuff() {
+    let board = Board::main();
+    let chunk_size = board.chunk_size();
+    let chunk_byte_size = board.packing().byte_size(chunk_size);
     println!(
         "playable area: {}x{}",
-        CHUNKS_IN_DIRECTION * CHUNK_SIZE,
-        CHUNKS_IN_DIRECTION * CHUNK_SIZE
+        CHUNKS_IN_DIRECTION * chunk_size,
+        CHUNKS_IN_DIRECTION * chunk_size
     );
-    let bytes = CHUNKS_IN_MAP * CHUNK_BYTE_SIZE;
+    let bytes = CHUNKS_IN_MAP * chunk_byte_size;
     println!("bytes for a map: {}", bytes);
     println!(
         "terrabits for a map: {:.4}",
@@ -26,18 +29,18 @@ fn calculate_stuff() {
     );
     println!();
     // size of each chunk
-    println!("chunk size: {}", CHUNK_SIZE);
-    println!("data size: {}", CHUNK_BYTE_SIZE);
+    println!("chunk size: {}", chunk_size);
+    println!("data size: {}", chunk_byte_size);
     println!(
         "data size MB: {:.4}",
-        (CHUNK_BYTE_SIZE) as f64 / 1024.0 / 1024.0
+        chunk_byte_size as f64 / 1024.0 / 1024.0
     );
 
     println!();
     let mut chunk = Chunk::new();
     chunk.apply_packed_cell(&PackedCell::new(0, 1).unwrap());
 
-    for i in 0..CHUNK_SIZE {
+    for i in 0..chunk_size {
         // random number between 0 and 15
         let value = rand::random::<u8>() % 16;
 
@@ -0,0 +1,133 @@
+use paintplayground::{chunk_db::SimpleToFileSaver, types::*};
+
+use crate::config::Config;
+use crate::{
+    board_manager::BoardManager,
+    tiles::{self, MAX_TILE_CACHE_ENTRIES, TileCache, TileCoordinates},
+};
+
+#[test]
+fn tiles_outside_of_the_board() {
+    let bounds = &Board::main().bounds();
+    let chunks_in_direction = bounds.max_x;
+    let max_zoom = tiles::max_zoom(bounds);
+
+    assert!(TileCoordinates::new(bounds, 0, chunks_in_direction, -chunks_in_direction).is_some());
+    assert!(TileCoordinates::new(bounds, 0, chunks_in_direction + 1, 0).is_none());
+
+    // the whole board fits in 2x2 tiles on the highest zoom
+    assert!(TileCoordinates::new(bounds, max_zoom, -1, -1).is_some());
+    assert!(TileCoordinates::new(bounds, max_zoom, 0, 0).is_some());
+    assert!(TileCoordinates::new(bounds, max_zoom, 1, 0).is_none());
+    assert!(TileCoordinates::new(bounds, max_zoom + 1, 0, 0).is_none());
+}
+
+#[test]
+fn tiles_of_other_bounds() {
+    // 8 chunks wide, 2 high
+    let wide = Bounds::new(4, 11, -1, 0).unwrap();
+    assert_eq!(tiles::max_zoom(&wide), 2);
+    assert!(TileCoordinates::new(&wide, 0, 4, 0).is_some());
+    assert!(TileCoordinates::new(&wide, 0, 3, 0).is_none());
+    assert!(TileCoordinates::new(&wide, 0, 4, 1).is_none());
+    assert!(TileCoordinates::new(&wide, 2, 1, -1).is_some());
+    assert!(TileCoordinates::new(&wide, 2, 3, -1).is_none());
+
+    // the zoom levels stop somewhere
+    let infinite = Bounds::INFINITE;
+    let max_zoom = tiles::max_zoom(&infinite);
+    assert!(TileCoordinates::new(&infinite, 0, i64::MIN, i64::MAX).is_some());
+    assert!(TileCoordinates::new(&infinite, max_zoom, 0, 0).is_some());
+    assert!(TileCoordinates::new(&infinite, max_zoom + 1, 0, 0).is_none());
+}
+
+#[test]
+fn tile_children_cover_the_tile() {
+    let tile = TileCoordinates::new(&Board::main().bounds(), 1, -1, 0).unwrap();
+    let children = tile.children().unwrap();
+
+    // top-left, top-right, bottom-left, bottom-right
+    let coordinates: Vec<(i64, i64)> = children.iter().map(|c| (c.x(), c.y())).collect();
+    assert_eq!(coordinates, vec![(-2, 1), (-1, 1), (-2, 0), (-1, 0)]);
+
+    for child in children {
+        assert!(child.children().is_none());
+        let chunk = ChunkCoordinates::new(child.x(), child.y()).unwrap();
+        assert_eq!(TileCoordinates::containing(chunk, 1), tile);
+    }
+}
+
+#[test]
+fn downsample_keeps_the_most_common_color() {
+    let mut child = Chunk::default();
+    let length = child.length();
+    // top-left block: 3 times Ten, once Two
+    child.set_pixel(0, Color::TEN);
+    child.set_pixel(1, Color::TEN);
+    child.set_pixel(length, Color::TEN);
+    child.set_pixel(length + 1, Color::TWO);
+    // next block: a tie, the top-left pixel wins
+    child.set_pixel(2, Color::FIVE);
+    child.set_pixel(3, Color::SIX);
+    child.set_pixel(length + 2, Color::FIVE);
+    child.set_pixel(length + 3, Color::SIX);
+
+    let mut target = Chunk::default();
+    tiles::downsample_into(&mut target, &child, 1, 1);
+
+    let half = length / 2;
+    let quadrant_start = half * length + half;
+    assert_eq!(target.pixel(quadrant_start).u8(), Color::TEN.u8());
+    assert_eq!(target.pixel(quadrant_start + 1).u8(), Color::FIVE.u8());
+    // the other quadrants are untouched
+    assert_eq!(target.pixel(0).u8(), Color::ZERO.u8());
+}
+
+#[tokio::test]
+async fn tile_renders_as_png() {
+    let communicator = BoardManager::start(SimpleToFileSaver::new(), &Config::default());
+
+    let tile = TileCoordinates::new(&Board::main().bounds(), 1, 0, 0).unwrap();
+    let png = communicator.get_tile(tile).await.unwrap();
+
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+}
+
+#[test]
+fn tile_cache_drops_the_least_recently_used() {
+    let cache = TileCache::new(Arc::new(Board::new(
+        "tiles".to_string(),
+        Bounds::INFINITE,
+        Palette::default(),
+        DEFAULT_CHUNK_LENGTH,
+        String::new(),
+    )));
+    let tile = |x: usize| TileCoordinates::new(&Bounds::INFINITE, 0, x as i64, 0).unwrap();
+    let png = axum::body::Bytes::from_static(b"png");
+
+    for x in 0..MAX_TILE_CACHE_ENTRIES {
+        cache.insert_png(tile(x), png.clone(), cache.generation(&tile(x)));
+    }
+    // the first tile is used again, so the second one is the oldest
+    assert!(cache.png(&tile(0)).is_some());
+    let last = tile(MAX_TILE_CACHE_ENTRIES);
+    cache.insert_png(last, png.clone(), cache.generation(&last));
+
+    assert!(cache.png(&tile(0)).is_some());
+    assert!(cache.png(&tile(1)).is_none());
+    assert!(cache.png(&tile(2)).is_some());
+    assert!(cache.png(&last).is_some());
+
+    // a render that started before the chunk changed is not cached
+    let generation = cache.generation(&tile(1));
+    cache.invalidate(ChunkCoordinates::new(1, 0).unwrap());
+    cache.insert_png(tile(1), png, generation);
+    assert!(cache.png(&tile(1)).is_none());
+}
+
+#[test]
+fn tile_budget_runs_out() {
+    let budget = tiles::TileBudget::default();
+    assert!((0..tiles::MAX_TILE_CHUNKS).all(|_| budget.take()));
+    assert!(!budget.take());
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use axum::body::Bytes;
+use tokio::sync::Semaphore;
+
+use paintplayground::types::*;
+
+/// When the cache holds this many tiles, the least recently used ones are dropped
+pub const MAX_TILE_CACHE_ENTRIES: usize = 10_000;
+
+/// Tiles whose invalidations are counted, above this the counts start over
+const MAX_TILE_INVALIDATIONS: usize = 100_000;
+
+/// How many chunks can be loaded at the same time while rendering a tile
+const TILE_LOAD_CONCURRENCY: usize = 64;
+
+/// Most chunks a single tile request loads, see [`TileBudget`]
+pub const MAX_TILE_CHUNKS: usize = 4096;
+
+/// Highest zoom level of huge and infinite boards.
+///
+/// A tile covers `4^z` chunks, so the top tiles of those boards don't show the whole board,
+/// and take a few requests to render, see [`MAX_TILE_CHUNKS`].
+const MAX_TILE_ZOOM: u8 = 10;
+
+/// A tile of the map pyramid.
+///
+/// At zoom 0 a tile is exactly one chunk, every zoom level above combines 2x2 tiles
+/// of the level below, so a tile at zoom `z` covers `2^z * 2^z` chunks.
+/// Every tile is rendered as a chunk of its board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCoordinates {
+    z: u8,
+    x: i64,
+    y: i64,
+}
+
+impl TileCoordinates {
+    /// A tile that covers at least one chunk of a board with these bounds
+    pub fn new(bounds: &Bounds, z: u8, x: i64, y: i64) -> Option<Self> {
+        if z > max_zoom(bounds) {
+            return None;
+        }
+
+        let tile = Self { z, x, y };
+        tile.intersects(bounds).then_some(tile)
+    }
+
+    /// At least one chunk of the tile is inside of the bounds
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        // shifting is a floor division, also for the negative side
+        (bounds.min_x >> self.z..=bounds.max_x >> self.z).contains(&self.x)
+            && (bounds.min_y >> self.z..=bounds.max_y >> self.z).contains(&self.y)
+    }
+
+    /// the tile at zoom `z` which contains the chunk
+    pub fn containing(coordinates: ChunkCoordinates, z: u8) -> Self {
+        Self {
+            z,
+            x: coordinates.x() >> z,
+            y: coordinates.y() >> z,
+        }
+    }
+
+    pub fn x(&self) -> i64 {
+        self.x
+    }
+
+    pub fn y(&self) -> i64 {
+        self.y
+    }
+
+    /// The four tiles one zoom level lower,
+    /// ordered top-left, top-right, bottom-left, bottom-right.
+    ///
+    /// y goes up, so the top row has the higher y.
+    pub fn children(&self) -> Option<[TileCoordinates; 4]> {
+        let z = self.z.checked_sub(1)?;
+        let (x, y) = (self.x * 2, self.y * 2);
+
+        Some([
+            Self { z, x, y: y + 1 },
+            Self {
+                z,
+                x: x + 1,
+                y: y + 1,
+            },
+            Self { z, x, y },
+            Self { z, x: x + 1, y },
+        ])
+    }
+}
+
+/// The zoom level at which the whole board fits in 2x2 tiles, at most [`MAX_TILE_ZOOM`]
+pub fn max_zoom(bounds: &Bounds) -> u8 {
+    let tiles = |min: i64, max: i64, zoom: u8| (max >> zoom) as i128 - (min >> zoom) as i128 + 1;
+
+    let mut zoom = 0;
+    while zoom < MAX_TILE_ZOOM
+        && (tiles(bounds.min_x, bounds.max_x, zoom) > 2
+            || tiles(bounds.min_y, bounds.max_y, zoom) > 2)
+    {
+        zoom += 1;
+    }
+    zoom
+}
+
+/// The most common color of the four, on a tie the first one wins.
+///
+/// Averaging would create colors which are not in the palette.
+fn mode(colors: [Color; 4]) -> Color {
+    let mut counts = [0u8; MAX_PALETTE_COLORS];
+    let mut best = colors[0];
+
+    for color in colors {
+        counts[color.u8() as usize] += 1;
+        if counts[color.u8() as usize] > counts[best.u8() as usize] {
+            best = color;
+        }
+    }
+
+    best
+}
+
+/// Downsample `child` by 2 and write it in a quadrant of `target`
+///
+/// quadrant_x 0 is left, quadrant_y 0 is top
+pub fn downsample_into(target: &mut Chunk, child: &Chunk, quadrant_x: usize, quadrant_y: usize) {
+    let length = target.length();
+    let half = length / 2;
+
+    for row in 0..half {
+        for column in 0..half {
+            let source = (row * 2) * length + column * 2;
+            let color = mode([
+                child.pixel(source),
+                child.pixel(source + 1),
+                child.pixel(source + length),
+                child.pixel(source + length + 1),
+            ]);
+
+            let target_row = quadrant_y * half + row;
+            let target_column = quadrant_x * half + column;
+            target.set_pixel(target_row * length + target_column, color);
+        }
+    }
+}
+
+/// The chunks a tile request can still load.
+///
+/// The chunks past it are left blank and the tiles containing them are not cached,
+/// the tiles that were rendered completely are, so the next request gets further.
+#[derive(Debug)]
+pub struct TileBudget(AtomicUsize);
+
+impl Default for TileBudget {
+    fn default() -> Self {
+        Self(AtomicUsize::new(MAX_TILE_CHUNKS))
+    }
+}
+
+impl TileBudget {
+    /// Use one chunk of the budget, `false` when it's used up
+    pub fn take(&self) -> bool {
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+    }
+}
+
+/// Rendered tiles, invalidated when a chunk inside of them changes
+///
+/// Holds at most [`MAX_TILE_CACHE_ENTRIES`] tiles, the least recently used are dropped first.
+#[derive(Debug)]
+pub struct TileCache {
+    inner: Mutex<TileCacheInner>,
+    /// its bounds decide the zoom levels
+    board: Arc<Board>,
+
+    /// limits the chunks being loaded by renders
+    pub loads: Semaphore,
+}
+
+#[derive(Debug, Default)]
+struct TileCacheInner {
+    entries: HashMap<TileCoordinates, TileEntry>,
+    /// last use of every entry, the first one is the least recently used
+    recency: BTreeMap<u64, TileCoordinates>,
+    /// counts the uses, to order [`Self::recency`]
+    uses: u64,
+    /// counts how often a tile got invalidated,
+    /// so a render that started before a change doesn't get cached
+    invalidations: HashMap<TileCoordinates, u64>,
+    /// bumped whenever `invalidations` is emptied to keep it small,
+    /// which outdates every render in progress
+    invalidations_cleared: u64,
+}
+
+#[derive(Debug, Default)]
+struct TileEntry {
+    /// downsampled tile for zoom > 0, used to build the next zoom level
+    mosaic: Option<Chunk>,
+    /// encoded png of the tile
+    png: Option<Bytes>,
+    last_use: u64,
+}
+
+/// Taken before rendering a tile, the render is only cached if it's still the same after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileGeneration {
+    cleared: u64,
+    invalidations: u64,
+}
+
+impl TileCacheInner {
+    fn generation(&self, tile: &TileCoordinates) -> TileGeneration {
+        TileGeneration {
+            cleared: self.invalidations_cleared,
+            invalidations: self.invalidations.get(tile).copied().unwrap_or(0),
+        }
+    }
+
+    /// The entry of the tile, marked as the most recently used
+    fn touch(&mut self, tile: &TileCoordinates) -> Option<&mut TileEntry> {
+        let entry = self.entries.get_mut(tile)?;
+        self.recency.remove(&entry.last_use);
+        self.uses += 1;
+        entry.last_use = self.uses;
+        self.recency.insert(self.uses, *tile);
+        Some(entry)
+    }
+
+    fn insert(
+        &mut self,
+        tile: TileCoordinates,
+        generation: TileGeneration,
+    ) -> Option<&mut TileEntry> {
+        if self.generation(&tile) != generation {
+            return None;
+        }
+
+        if !self.entries.contains_key(&tile) {
+            while self.entries.len() >= MAX_TILE_CACHE_ENTRIES {
+                let Some((_, oldest)) = self.recency.pop_first() else {
+                    break;
+                };
+                self.entries.remove(&oldest);
+            }
+            self.entries.insert(tile, TileEntry::default());
+        }
+        self.touch(&tile)
+    }
+}
+
+impl TileCache {
+    /// Cache of the tiles of the board
+    pub fn new(board: Arc<Board>) -> Self {
+        Self {
+            inner: Mutex::new(TileCacheInner::default()),
+            board,
+            loads: Semaphore::new(TILE_LOAD_CONCURRENCY),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TileCacheInner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Take before rendering, and pass to the `insert_` functions
+    pub fn generation(&self, tile: &TileCoordinates) -> TileGeneration {
+        self.lock().generation(tile)
+    }
+
+    pub fn mosaic(&self, tile: &TileCoordinates) -> Option<Chunk> {
+        self.lock().touch(tile)?.mosaic.clone()
+    }
+
+    pub fn insert_mosaic(&self, tile: TileCoordinates, mosaic: Chunk, generation: TileGeneration) {
+        if let Some(entry) = self.lock().insert(tile, generation) {
+            entry.mosaic = Some(mosaic);
+        }
+    }
+
+    pub fn png(&self, tile: &TileCoordinates) -> Option<Bytes> {
+        self.lock().touch(tile)?.png.clone()
+    }
+
+    pub fn insert_png(&self, tile: TileCoordinates, png: Bytes, generation: TileGeneration) {
+        if let Some(entry) = self.lock().insert(tile, generation) {
+            entry.png = Some(png);
+        }
+    }
+
+    /// The chunk changed, forget every tile containing it
+    pub fn invalidate(&self, coordinates: ChunkCoordinates) {
+        let max_zoom = max_zoom(&self.board.bounds());
+        let mut inner = self.lock();
+
+        if inner.invalidations.len() >= MAX_TILE_INVALIDATIONS {
+            inner.invalidations.clear();
+            inner.invalidations_cleared += 1;
+        }
+
+        // tiles outside of the bounds were blank, so growing them doesn't outdate a cached tile
+        for z in 0..=max_zoom {
+            let tile = TileCoordinates::containing(coordinates, z);
+
+            *inner.invalidations.entry(tile).or_insert(0) += 1;
+            if let Some(entry) = inner.entries.remove(&tile) {
+                inner.recency.remove(&entry.last_use);
+            }
+        }
+    }
+}
@@ -1,9 +1,8 @@
-use std::env;
 pub use std::fmt::Debug;
 use std::ops::Deref;
 use std::ops::DerefMut;
 pub use std::sync::Arc;
-use std::sync::LazyLock;
+use std::sync::RwLock;
 
 pub use tokio::sync::broadcast;
 pub use tokio::sync::mpsc;
@@ -15,107 +14,341 @@ pub use tracing::{debug, error, info, warn};
 
 // pub type BoardRequester = mpsc::Sender<oneshot::Sender<Arc<RwLock<Chunk>>>>;
 
-pub const CHUNK_LENGTH: usize = 100;
-pub const CHUNK_SIZE: usize = CHUNK_LENGTH * CHUNK_LENGTH;
-pub const CHUNK_BYTE_SIZE: usize = CHUNK_SIZE / 2;
+/// Pixels in a row and rows in a chunk of a board without a `chunk_length`
+pub const DEFAULT_CHUNK_LENGTH: usize = 100;
+/// Chunks stored without a [`StorageHeader`] are always this long
+pub const LEGACY_CHUNK_LENGTH: usize = 100;
+/// Size of the raw chunks stored without a format byte
+pub const LEGACY_CHUNK_BYTE_SIZE: usize = LEGACY_CHUNK_LENGTH * LEGACY_CHUNK_LENGTH / 2;
 
-// get this from env
-pub static CHUNKS_IN_DIRECTION: LazyLock<i64> = LazyLock::new(|| {
-    let number = env::var("CHUNKS_IN_DIRECTION")
-        .unwrap_or({
-            info!("CHUNKS_IN_DIRECTION not set, using 10");
-            "10".to_string()
+pub const DEFAULT_CHUNKS_IN_DIRECTION: i64 = 10;
+
+/// The chunks of a board, a rectangle including its edges.
+///
+/// `"infinite"` in the config is the whole range of i64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "BoundsSetting")]
+pub struct Bounds {
+    pub min_x: i64,
+    pub max_x: i64,
+    pub min_y: i64,
+    pub max_y: i64,
+}
+
+/// How [`Bounds`] are written, a rectangle or `"infinite"`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BoundsSetting {
+    Named(String),
+    Rectangle {
+        min_x: i64,
+        max_x: i64,
+        min_y: i64,
+        max_y: i64,
+    },
+}
+
+impl TryFrom<BoundsSetting> for Bounds {
+    type Error = String;
+
+    fn try_from(setting: BoundsSetting) -> Result<Self, String> {
+        match setting {
+            BoundsSetting::Named(name) if name == "infinite" => Ok(Self::INFINITE),
+            BoundsSetting::Named(name) => Err(format!(
+                "bounds are \"infinite\" or min_x, max_x, min_y and max_y, got {:?}",
+                name
+            )),
+            BoundsSetting::Rectangle {
+                min_x,
+                max_x,
+                min_y,
+                max_y,
+            } => Self::new(min_x, max_x, min_y, max_y),
+        }
+    }
+}
+
+impl Bounds {
+    /// Limited only by i64
+    pub const INFINITE: Self = Self {
+        min_x: i64::MIN,
+        max_x: i64::MAX,
+        min_y: i64::MIN,
+        max_y: i64::MAX,
+    };
+
+    pub fn new(min_x: i64, max_x: i64, min_y: i64, max_y: i64) -> Result<Self, String> {
+        if min_x > max_x || min_y > max_y {
+            return Err(format!(
+                "bounds need min_x <= max_x and min_y <= max_y, got x {}..={} and y {}..={}",
+                min_x, max_x, min_y, max_y
+            ));
+        }
+
+        Ok(Self {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
         })
-        // .expect("CHUNKS_IN_DIRECTION environment variable not set")
-        .parse()
-        .expect("CHUNKS_IN_DIRECTION is not a unsigned number");
-    if number < 0 {
-        panic!("CHUNKS_IN_DIRECTION cannot be negative")
     }
-    number
-});
-// pub const CHUNKS: usize = CHUNKS_IN_DIRECTION * CHUNKS_IN_DIRECTION;
+
+    /// Reaching `chunks_in_direction` from the center in every direction
+    pub fn square(chunks_in_direction: i64) -> Self {
+        Self {
+            min_x: -chunks_in_direction,
+            max_x: chunks_in_direction,
+            min_y: -chunks_in_direction,
+            max_y: chunks_in_direction,
+        }
+    }
+
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        (self.min_x..=self.max_x).contains(&x) && (self.min_y..=self.max_y).contains(&y)
+    }
+
+    /// Every chunk of `other` is inside of these bounds
+    pub fn contains_bounds(&self, other: &Bounds) -> bool {
+        self.contains(other.min_x, other.min_y) && self.contains(other.max_x, other.max_y)
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        *self == Self::INFINITE
+    }
+
+    /// The smallest bounds containing both
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min_x: self.min_x.min(other.min_x),
+            max_x: self.max_x.max(other.max_x),
+            min_y: self.min_y.min(other.min_y),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("the bounds of a board can only grow, {new:?} doesn't contain {current:?}")]
+pub struct ShrinkError {
+    pub current: Bounds,
+    pub new: Bounds,
+}
+
+/// Name of the board of the top level config, served without a `/b/{name}` prefix
+pub const MAIN_BOARD: &str = "main";
+
+/// A board of the server, every board has its own chunks, size and colors.
+#[derive(Debug)]
+pub struct Board {
+    pub name: String,
+    /// can grow while the board is running
+    bounds: RwLock<Bounds>,
+    pub palette: Palette,
+    /// pixels in a row and rows in every chunk of the board
+    pub chunk_length: usize,
+    /// in front of the storage paths of its chunks, empty for the main board
+    pub storage_prefix: String,
+    /// how its chunks are compressed when they are saved, chunks stored before keep their format
+    pub compression: CompressionPolicy,
+    /// of the chunks stored with a dictionary, none unless they are loaded
+    pub dictionaries: Arc<Dictionaries>,
+}
+
+impl Board {
+    pub fn new(
+        name: String,
+        bounds: Bounds,
+        palette: Palette,
+        chunk_length: usize,
+        storage_prefix: String,
+    ) -> Self {
+        Self {
+            name,
+            bounds: RwLock::new(bounds),
+            palette,
+            chunk_length,
+            storage_prefix,
+            compression: DEFAULT_COMPRESSION,
+            dictionaries: Default::default(),
+        }
+    }
+
+    /// The main board of a default config
+    pub fn main() -> Self {
+        Self::new(
+            MAIN_BOARD.to_string(),
+            Bounds::square(DEFAULT_CHUNKS_IN_DIRECTION),
+            Palette::default(),
+            DEFAULT_CHUNK_LENGTH,
+            String::new(),
+        )
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        *self
+            .bounds
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Replace the bounds with bigger ones, the chunks outside of the current bounds are blank
+    pub fn grow(&self, bounds: Bounds) -> Result<(), ShrinkError> {
+        let mut current = self
+            .bounds
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Self::check_growth(&current, bounds)?;
+
+        *current = bounds;
+        Ok(())
+    }
+
+    /// Whether [`grow`](Board::grow) would accept the bounds
+    pub fn can_grow(&self, bounds: Bounds) -> Result<(), ShrinkError> {
+        Self::check_growth(&self.bounds(), bounds)
+    }
+
+    fn check_growth(current: &Bounds, bounds: Bounds) -> Result<(), ShrinkError> {
+        match bounds.contains_bounds(current) {
+            true => Ok(()),
+            false => Err(ShrinkError {
+                current: *current,
+                new: bounds,
+            }),
+        }
+    }
+
+    /// How the pixels of its chunks are packed, follows from the palette
+    pub fn packing(&self) -> Packing {
+        self.palette.packing()
+    }
+
+    /// Coordinates of a chunk on this board
+    pub fn coordinates(&self, x: i64, y: i64) -> Result<ChunkCoordinates, OutOfBoundsError> {
+        ChunkCoordinates::within(x, y, &self.bounds())
+    }
+
+    /// Pixels in a chunk
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_length * self.chunk_length
+    }
+
+    /// A chunk where nothing is painted yet
+    pub fn blank_chunk(&self) -> Chunk {
+        Chunk::blank_with(self.chunk_length, self.packing())
+    }
+}
 
 pub const MB: u64 = 1024 * 1024;
 pub const CACHE_SIZE: u64 = 100 * MB;
 
-#[derive(Debug, Clone, Copy)]
-#[repr(u8)]
-/// Represents the possible colors of a cell.
+/// A color of the board's [`Palette`], its index in there.
 ///
-/// The colors are taken from the 4-bit RGB palette from Lospec.
-/// https://lospec.com/palette-list/woodspark
-pub enum Color {
-    ///  #e0d3c8
-    Zero = 0b0000,
-    One = 0b0001,
-    Two = 0b0010,
-    Three = 0b0011,
-    Four = 0b0100,
-    Five = 0b0101,
-    Six = 0b0110,
-    Seven = 0b0111,
-    Eight = 0b1000,
-    Nine = 0b1001,
-    Ten = 0b1010,
-    Eleven = 0b1011,
-    Twelve = 0b1100,
-    Thirteen = 0b1101,
-    Fourteen = 0b1110,
-    Fifteen = 0b1111,
-}
+/// The first 16 are named, as every palette with 4 bit packing has at most those.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color(u8);
 
 impl Color {
+    ///  #e0d3c8 in the default palette, the background
+    pub const ZERO: Color = Color(0);
+    pub const ONE: Color = Color(1);
+    pub const TWO: Color = Color(2);
+    pub const THREE: Color = Color(3);
+    pub const FOUR: Color = Color(4);
+    pub const FIVE: Color = Color(5);
+    pub const SIX: Color = Color(6);
+    pub const SEVEN: Color = Color(7);
+    pub const EIGHT: Color = Color(8);
+    pub const NINE: Color = Color(9);
+    pub const TEN: Color = Color(10);
+    pub const ELEVEN: Color = Color(11);
+    pub const TWELVE: Color = Color(12);
+    pub const THIRTEEN: Color = Color(13);
+    pub const FOURTEEN: Color = Color(14);
+    pub const FIFTEEN: Color = Color(15);
+
+    /// `None` when the value isn't one of the 16 named colors
     fn new(value: u8) -> Option<Self> {
-        Some(match value {
-            0 => Color::Zero,
-            1 => Color::One,
-            2 => Color::Two,
-            3 => Color::Three,
-            4 => Color::Four,
-            5 => Color::Five,
-            6 => Color::Six,
-            7 => Color::Seven,
-            8 => Color::Eight,
-            9 => Color::Nine,
-            10 => Color::Ten,
-            11 => Color::Eleven,
-            12 => Color::Twelve,
-            13 => Color::Thirteen,
-            14 => Color::Fourteen,
-            15 => Color::Fifteen,
-            _ => return None,
-        })
+        (value < 16).then_some(Self(value))
     }
 
     pub fn u8(self) -> u8 {
-        self as u8
+        self.0
     }
 
-    /// this color as the rgb value from the pallet
-    pub fn to_rgb(&self) -> (u8, u8, u8) {
-        match self {
-            Color::Zero => (224, 211, 200),
-            Color::One => (245, 238, 176),
-            Color::Two => (250, 191, 97),
-            Color::Three => (224, 141, 81),
-            Color::Four => (138, 88, 101),
-            Color::Five => (69, 43, 63),
-            Color::Six => (44, 94, 59),
-            Color::Seven => (96, 156, 79),
-            Color::Eight => (198, 204, 84),
-            Color::Nine => (120, 194, 214),
-            Color::Ten => (84, 121, 176),
-            Color::Eleven => (86, 84, 110),
-            Color::Twelve => (131, 158, 166),
-            Color::Thirteen => (240, 91, 91),
-            Color::Fourteen => (143, 50, 95),
-            Color::Fifteen => (235, 108, 152),
-        }
-    }
-
-    pub fn all_colors_rgb() -> [(u8, u8, u8); 16] {
-        [
+    /// index of the color in the palette of a png
+    pub fn to_index(&self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Color {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match Color::new(value) {
+            Some(color) => Ok(color),
+            None => Err(()),
+        }
+    }
+}
+
+/// A color of a [`Palette`], written as `"#rrggbb"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl std::str::FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid color {:?}, expected #rrggbb", value);
+
+        let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| invalid());
+        Ok(Self(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(Rgb(r, g, b): Rgb) -> Self {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// Most colors a palette can have, a pixel is at most a byte
+pub const MAX_PALETTE_COLORS: usize = 256;
+
+/// The colors a board is painted with, [`Color`] is an index in it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<Rgb>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::woodspark()
+    }
+}
+
+impl Palette {
+    /// The 4-bit RGB palette from Lospec.
+    /// https://lospec.com/palette-list/woodspark
+    pub fn woodspark() -> Self {
+        let colors = [
             (224, 211, 200),
             (245, 238, 176),
             (250, 191, 97),
@@ -132,50 +365,94 @@ impl Color {
             (240, 91, 91),
             (143, 50, 95),
             (235, 108, 152),
-        ]
+        ];
+
+        Self {
+            name: "woodspark".to_string(),
+            colors: colors.iter().map(|&(r, g, b)| Rgb(r, g, b)).collect(),
+        }
     }
 
-    pub fn to_index(&self) -> u8 {
-        let (r, g, b) = self.to_rgb();
-        Self::rgb_to_index(r, g, b)
+    /// Up to 16 colors fit in 4 bits, more need a byte per pixel
+    pub fn packing(&self) -> Packing {
+        if self.colors.len() <= 16 {
+            Packing::Nibble
+        } else {
+            Packing::Byte
+        }
     }
 
-    // we use all colours to get the correct index for the mapped colours
-    pub fn rgb_to_index(r: u8, g: u8, b: u8) -> u8 {
-        let colors = Color::all_colors_rgb();
-        for (idx, &(cr, cg, cb)) in colors.iter().enumerate() {
-            if r == cr && g == cg && b == cb {
-                return idx as u8;
-            }
+    /// The color at this index, `None` when the palette has no color there
+    pub fn color(&self, value: u8) -> Option<Color> {
+        ((value as usize) < self.colors.len()).then_some(Color(value))
+    }
+
+    /// The rgb value of the color, the first color for one which isn't in the palette
+    pub fn rgb(&self, color: Color) -> Rgb {
+        self.colors
+            .get(color.u8() as usize)
+            .or(self.colors.first())
+            .copied()
+            .unwrap_or(Rgb(0, 0, 0))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.colors.is_empty() || self.colors.len() > MAX_PALETTE_COLORS {
+            return Err(format!(
+                "palette {:?} needs between 1 and {} colors, got {}",
+                self.name,
+                MAX_PALETTE_COLORS,
+                self.colors.len()
+            ));
         }
-        // Default to first color if no match (shouldn't happen)
-        0
+        Ok(())
     }
 }
 
-impl TryFrom<u8> for Color {
-    type Error = ();
+/// How pixels are packed in the bytes of a [`Chunk`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packing {
+    /// two 4 bit pixels per byte, for up to 16 colors
+    Nibble,
+    /// a pixel per byte, for up to 256 colors
+    Byte,
+}
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match Color::new(value) {
-            Some(color) => Ok(color),
-            None => Err(()),
+impl Packing {
+    pub fn bits(self) -> usize {
+        match self {
+            Self::Nibble => 4,
+            Self::Byte => 8,
         }
     }
+
+    /// Bytes needed for this many pixels
+    pub fn byte_size(self, pixels: usize) -> usize {
+        pixels * self.bits() / 8
+    }
+
+    /// The packing which makes `pixels` fit in `bytes`
+    pub fn for_byte_size(pixels: usize, bytes: usize) -> Option<Self> {
+        [Self::Nibble, Self::Byte]
+            .into_iter()
+            .find(|packing| packing.byte_size(pixels) == bytes)
+    }
 }
 
 /// a u8 keeps two colors, as each color is 4 bits.
 ///
 /// This is done to reduce the memory footprint of the board.
+/// With [`Packing::Byte`] it keeps a single color instead, see [`ChunkColor::single`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct ChunkColor(u8);
 
 impl Default for ChunkColor {
     fn default() -> Self {
-        Self::new(Color::Zero, Color::Zero)
+        Self::new(Color::ZERO, Color::ZERO)
     }
 }
 
+/// Two 4 bit colors of the palette
 impl TryFrom<u8> for ChunkColor {
     type Error = ();
 
@@ -187,15 +464,25 @@ impl TryFrom<u8> for ChunkColor {
     }
 }
 
-impl Into<u8> for ChunkColor {
-    fn into(self) -> u8 {
-        self.0
+impl From<ChunkColor> for u8 {
+    fn from(val: ChunkColor) -> Self {
+        val.0
     }
 }
 
 impl ChunkColor {
     pub fn new(color1: Color, color2: Color) -> Self {
-        ChunkColor(((color1 as u8) << 4) | color2 as u8)
+        ChunkColor((color1.u8() << 4) | color2.u8())
+    }
+
+    /// A byte with a single color, for [`Packing::Byte`]
+    pub fn single(color: Color) -> Self {
+        ChunkColor(color.u8())
+    }
+
+    /// The color of a byte with a single color
+    pub fn color(&self) -> Color {
+        Color(self.0)
     }
 
     // get the left color of the packed u8
@@ -206,7 +493,7 @@ impl ChunkColor {
     }
 
     pub fn left_color(&self) -> Color {
-        Color::new(self.left()).unwrap()
+        Color(self.left())
     }
 
     // get the right color of the packed u8
@@ -217,7 +504,7 @@ impl ChunkColor {
     }
 
     pub fn right_color(&self) -> Color {
-        Color::new(self.right()).unwrap()
+        Color(self.right())
     }
 
     pub fn set_left(&mut self, color: Color) {
@@ -229,187 +516,740 @@ impl ChunkColor {
     }
 }
 
-pub const USED_COMPRESSION: CompressionType = CompressionType::Zstd;
+/// Compression of stored chunks, unless the config has a `storage.compression`
+pub const DEFAULT_COMPRESSION: CompressionPolicy =
+    CompressionPolicy::Fixed(CompressionType::Zstd(DEFAULT_ZSTD_LEVEL));
+
+/// Why stored bytes are not a chunk
+#[derive(thiserror::Error, Debug)]
+pub enum DecodeError {
+    #[error("empty data")]
+    Empty,
+    #[error("unknown format byte {0}")]
+    UnknownFormat(u8),
+    #[error("{codec} decompression failed: {source}")]
+    Decompression {
+        codec: &'static str,
+        source: CompressionError,
+    },
+    #[error("wrong size, expected {expected} bytes, got {got}")]
+    WrongSize { expected: usize, got: usize },
+    #[error("invalid color {0}")]
+    InvalidColor(u8),
+    /// compressed with a dictionary which isn't loaded, the data can still be fine
+    #[error("dictionary {0} is not loaded")]
+    UnknownDictionary(u8),
+    #[error("header is cut off, {0} bytes")]
+    TruncatedHeader(usize),
+    #[error("unsupported header version {0}")]
+    UnsupportedVersion(u8),
+    /// stored by a board with other chunk dimensions
+    #[error("chunk is {width}x{height}, expected {expected}x{expected}")]
+    WrongDimensions {
+        width: u16,
+        height: u16,
+        expected: usize,
+    },
+    /// stored with a palette of another size
+    #[error("chunk has {got} bits per pixel, the palette needs {expected}")]
+    WrongPacking { expected: usize, got: usize },
+    #[error("checksum mismatch, expected {expected:08x}, got {got:08x}")]
+    ChecksumMismatch { expected: u32, got: u32 },
+}
+
+/// Start of every stored chunk with a [`StorageHeader`]
+pub const STORAGE_MAGIC: [u8; 4] = *b"PPCK";
+/// Version of the [`StorageHeader`] written by [`Chunk::to_storage_bytes`]
+pub const STORAGE_VERSION: u8 = 1;
+/// magic, version, codec, width, height, uncompressed length and checksum
+pub const STORAGE_HEADER_SIZE: usize = 4 + 1 + 1 + 2 + 2 + 4 + 4;
+
+/// Header in front of a stored chunk, so damaged or cut off data is noticed.
+///
+/// Little endian, after the [`STORAGE_MAGIC`]. The payload follows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageHeader {
+    pub version: u8,
+    /// the format byte of the payload: 0 raw, 1 zstd, 2 lz4, 3 single color, 4 rle, 5 rle-zstd, 6 zstd-dict
+    pub codec: u8,
+    pub width: u16,
+    pub height: u16,
+    /// bytes of the packed pixels
+    pub uncompressed_length: u32,
+    /// CRC32 of the packed pixels
+    pub checksum: u32,
+}
+
+impl StorageHeader {
+    fn new(codec: u8, length: usize, packing: Packing, checksum: u32) -> Self {
+        Self {
+            version: STORAGE_VERSION,
+            codec,
+            width: length as u16,
+            height: length as u16,
+            uncompressed_length: packing.byte_size(length * length) as u32,
+            checksum,
+        }
+    }
+
+    /// How the pixels are packed, `None` if the length fits neither
+    pub fn packing(&self) -> Option<Packing> {
+        Packing::for_byte_size(
+            self.width as usize * self.height as usize,
+            self.uncompressed_length as usize,
+        )
+    }
+
+    /// `None` when the data doesn't start with the magic, stored before there was a header
+    pub fn parse(data: &[u8]) -> Option<Result<Self, DecodeError>> {
+        if !data.starts_with(&STORAGE_MAGIC) {
+            return None;
+        }
+        let Some(header) = data.get(..STORAGE_HEADER_SIZE) else {
+            return Some(Err(DecodeError::TruncatedHeader(data.len())));
+        };
+
+        let u16_at = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+        let u32_at = |at: usize| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+
+        Some(Ok(Self {
+            version: header[4],
+            codec: header[5],
+            width: u16_at(6),
+            height: u16_at(8),
+            uncompressed_length: u32_at(10),
+            checksum: u32_at(14),
+        }))
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&STORAGE_MAGIC);
+        output.push(self.version);
+        output.push(self.codec);
+        output.extend_from_slice(&self.width.to_le_bytes());
+        output.extend_from_slice(&self.height.to_le_bytes());
+        output.extend_from_slice(&self.uncompressed_length.to_le_bytes());
+        output.extend_from_slice(&self.checksum.to_le_bytes());
+    }
+
+    /// Decode the payload after the header, and check it against the header
+    fn decode(&self, payload: &[u8], board: &Board) -> Result<Chunk, DecodeError> {
+        if self.version != STORAGE_VERSION {
+            return Err(DecodeError::UnsupportedVersion(self.version));
+        }
+        // a board never mixes chunk sizes
+        let length = board.chunk_length;
+        if (self.width as usize, self.height as usize) != (length, length) {
+            return Err(DecodeError::WrongDimensions {
+                width: self.width,
+                height: self.height,
+                expected: length,
+            });
+        }
+        let expected_packing = board.packing();
+        if let Some(packing) = self
+            .packing()
+            .filter(|packing| *packing != expected_packing)
+        {
+            return Err(DecodeError::WrongPacking {
+                expected: expected_packing.bits(),
+                got: packing.bits(),
+            });
+        }
+        let expected_size = expected_packing.byte_size(board.chunk_size());
+        if self.uncompressed_length as usize != expected_size {
+            return Err(DecodeError::WrongSize {
+                expected: expected_size,
+                got: self.uncompressed_length as usize,
+            });
+        }
+
+        let chunk = Chunk::decode_payload(self.codec, payload, board)?;
+
+        let checksum = crc32fast::hash(&chunk.clone().to_u8vec());
+        if checksum != self.checksum {
+            return Err(DecodeError::ChecksumMismatch {
+                expected: self.checksum,
+                got: checksum,
+            });
+        }
+
+        Ok(chunk)
+    }
+}
+
+/// The format byte of stored data, the codec of its header if it has one
+pub fn storage_format(data: &[u8]) -> Option<u8> {
+    match StorageHeader::parse(data) {
+        Some(Ok(header)) => Some(header.codec),
+        _ => data.first().copied(),
+    }
+}
 
+/// Width and height of the stored chunk, `None` when its header is damaged
+pub fn storage_dimensions(data: &[u8]) -> Option<(u16, u16)> {
+    match StorageHeader::parse(data) {
+        Some(Ok(header)) => Some((header.width, header.height)),
+        Some(Err(_)) => None,
+        None if data.is_empty() => None,
+        None => Some((LEGACY_CHUNK_LENGTH as u16, LEGACY_CHUNK_LENGTH as u16)),
+    }
+}
+
+/// How the pixels of the stored chunk are packed, `None` when its header is damaged
+pub fn storage_packing(data: &[u8]) -> Option<Packing> {
+    match StorageHeader::parse(data) {
+        Some(Ok(header)) => header.packing(),
+        Some(Err(_)) => None,
+        None if data.is_empty() => None,
+        None => Some(Packing::Nibble),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompressionType {
     None,
-    Zstd,
+    /// zstd at this level, the level isn't needed to decompress
+    Zstd(i32),
     Lz4,
+    /// zstd with the trained dictionary of this id
+    ZstdDict(u8),
+    /// run-length encoding of the colors, good for pixel art
+    Rle,
+    /// run-length encoding followed by zstd
+    RleZstd,
     // Gzip, // it is very compact, but very slow
 }
 
-type ChunkArray<const N: usize> = [ChunkColor; N];
-// type ChunkArray = [ChunkColor; CHUNK_SIZE / 2];
+impl CompressionType {
+    /// Whether the codec can store chunks with this packing, the rle runs are of 4 bit colors
+    pub fn supports(self, packing: Packing) -> bool {
+        packing == Packing::Nibble || !matches!(self, Self::Rle | Self::RleZstd)
+    }
+}
+
+impl std::str::FromStr for CompressionType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Self::None),
+            "zstd" => Ok(Self::Zstd(DEFAULT_ZSTD_LEVEL)),
+            "lz4" => Ok(Self::Lz4),
+            "rle" => Ok(Self::Rle),
+            "rle-zstd" => Ok(Self::RleZstd),
+            other => {
+                if let Some(level) = other.strip_prefix("zstd:") {
+                    return match level.parse() {
+                        Ok(level) if zstd::compression_level_range().contains(&level) => {
+                            Ok(Self::Zstd(level))
+                        }
+                        _ => Err(format!(
+                            "invalid zstd level {:?}, expected {:?}",
+                            level,
+                            zstd::compression_level_range()
+                        )),
+                    };
+                }
+
+                match other.strip_prefix("zstd-dict:").map(str::parse) {
+                    Some(Ok(id)) => Ok(Self::ZstdDict(id)),
+                    _ => Err(format!(
+                        "unknown compression {:?}, expected none, zstd, zstd:{{level}}, zstd-dict:{{id}}, lz4, rle or rle-zstd",
+                        other
+                    )),
+                }
+            }
+        }
+    }
+}
 
-pub type Chunk = InnerChunk<{ CHUNK_SIZE / 2 }>;
+/// Which codecs [`Chunk::to_storage_bytes`] uses
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum CompressionPolicy {
+    /// Always this codec, `"zstd:3"`
+    Fixed(CompressionType),
+    /// Try every codec and keep the smallest result, `"smallest:lz4,zstd:19"`
+    Smallest(Vec<CompressionType>),
+}
 
-pub type SmallChunkArray = InnerChunk<5>;
+impl CompressionPolicy {
+    /// Codecs tried by `"smallest"` without a list
+    pub fn default_candidates() -> Vec<CompressionType> {
+        vec![
+            CompressionType::Lz4,
+            CompressionType::Zstd(3),
+            CompressionType::RleZstd,
+        ]
+    }
+
+    pub fn codecs(&self) -> &[CompressionType] {
+        match self {
+            Self::Fixed(compression) => std::slice::from_ref(compression),
+            Self::Smallest(candidates) => candidates,
+        }
+    }
+
+    /// A dictionary used by the policy which isn't one of the `dictionaries`
+    pub fn missing_dictionary(&self, dictionaries: &Dictionaries) -> Option<u8> {
+        self.codecs()
+            .iter()
+            .find_map(|compression| match compression {
+                CompressionType::ZstdDict(id) if !dictionaries.contains(*id) => Some(*id),
+                _ => None,
+            })
+    }
+
+    /// A codec of the policy which can't store chunks with this packing
+    pub fn unsupported_codec(&self, packing: Packing) -> Option<CompressionType> {
+        self.codecs()
+            .iter()
+            .copied()
+            .find(|compression| !compression.supports(packing))
+    }
+}
+
+impl From<CompressionType> for CompressionPolicy {
+    fn from(compression: CompressionType) -> Self {
+        Self::Fixed(compression)
+    }
+}
+
+impl std::str::FromStr for CompressionPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "smallest" {
+            return Ok(Self::Smallest(Self::default_candidates()));
+        }
+
+        match value.strip_prefix("smallest:") {
+            Some(list) => list
+                .split(',')
+                .map(|codec| codec.trim().parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map(Self::Smallest),
+            None => value.parse().map(Self::Fixed),
+        }
+    }
+}
+
+impl TryFrom<String> for CompressionPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
 
 impl Chunk {
     pub fn row_of_colors(&self, x: usize) -> Vec<Color> {
-        let start = x * (CHUNK_LENGTH / 2);
-        let end = start + (CHUNK_LENGTH / 2);
+        let length = self.length();
+        let start = x * length;
 
-        // Collect both left and right colors for the entire row
-        self.0[start..end]
-            .iter()
-            .flat_map(|chunk_color| vec![chunk_color.left_color(), chunk_color.right_color()])
+        (start..start + length)
+            .map(|index| self.pixel(index))
             .collect()
     }
 
-    pub fn from_raw_data(data: &[u8]) -> Result<Self, String> {
+    /// Decode stored bytes of the main board, telling what is wrong with them if they can't be
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode_for(data, &Board::main())
+    }
+
+    /// Decode stored bytes of a chunk of the board
+    pub fn decode_for(data: &[u8], board: &Board) -> Result<Self, DecodeError> {
         if data.is_empty() {
-            return Err("empty data".into());
+            return Err(DecodeError::Empty);
+        }
+
+        if let Some(header) = StorageHeader::parse(data) {
+            match header.and_then(|header| header.decode(&data[STORAGE_HEADER_SIZE..], board)) {
+                Ok(mut chunk) => {
+                    // checked, so it can be sent as it is until the chunk changes
+                    chunk.stored = Some(data.into());
+                    return Ok(chunk);
+                }
+                // raw pixels of the old format can start like the magic
+                Err(_) if data.len() == LEGACY_CHUNK_BYTE_SIZE => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        // everything stored before the header has the legacy dimensions
+        if board.chunk_length != LEGACY_CHUNK_LENGTH {
+            return Err(DecodeError::WrongDimensions {
+                width: LEGACY_CHUNK_LENGTH as u16,
+                height: LEGACY_CHUNK_LENGTH as u16,
+                expected: board.chunk_length,
+            });
+        }
+        if board.packing() != Packing::Nibble {
+            return Err(DecodeError::WrongPacking {
+                expected: board.packing().bits(),
+                got: Packing::Nibble.bits(),
+            });
         }
 
         // if we are reading exactly byte size, we have an old uncompressed format
-        if data.len() == CHUNK_BYTE_SIZE {
-            return Ok(data.to_vec().into());
+        if data.len() == LEGACY_CHUNK_BYTE_SIZE {
+            return Self::from_packed(data.to_vec(), LEGACY_CHUNK_LENGTH, &board.palette);
         }
 
-        let format = data[0];
-        let content = &data[1..];
+        // a format byte without a header, from before the header was added
+        Self::decode_payload(data[0], &data[1..], board)
+    }
 
+    /// Decode the payload of a format byte (the codec of the header)
+    fn decode_payload(format: u8, content: &[u8], board: &Board) -> Result<Self, DecodeError> {
+        let palette = &board.palette;
         match format {
-            0 => {
-                if content.len() != CHUNK_BYTE_SIZE {
-                    return Err("Invalid chunk size with uncompressed data".into());
-                }
-                Ok(content.to_vec().into())
-            }
+            0 => Self::from_packed(content.to_vec(), board.chunk_length, palette),
             // ZSTD compressed
-            1 => {
-                let uncompressed =
-                    ZstdCompression::decompress(content, CHUNK_BYTE_SIZE).map_err(|err| {
-                        let text = format!("zstd decompression failed: {}", err);
-                        error!(text);
-                        text
-                    })?;
-                Ok(uncompressed.into())
-            }
+            1 => Self::decompress_with::<ZstdCompression>(content, board),
             // Lz4 compressed
-            2 => {
+            2 => Self::decompress_with::<LZ4Compression>(content, board),
+            // a single color, the whole chunk is that color
+            3 => {
+                let [color] = content else {
+                    return Err(DecodeError::WrongSize {
+                        expected: 1,
+                        got: content.len(),
+                    });
+                };
+                palette
+                    .color(*color)
+                    .map(|color| Self::filled_with(color, board.chunk_length, palette.packing()))
+                    .ok_or(DecodeError::InvalidColor(*color))
+            }
+            // run-length encoded colors
+            4 => Self::decompress_with::<RleCompression>(content, board),
+            // run-length encoded colors, zstd compressed
+            5 => Self::decompress_with::<RleZstdCompression>(content, board),
+            // zstd with a trained dictionary, its id is the next byte
+            6 => {
+                let expected_size = palette.packing().byte_size(board.chunk_size());
+                let (id, content) = content.split_first().ok_or(DecodeError::WrongSize {
+                    expected: expected_size,
+                    got: 0,
+                })?;
+                let Ok(dictionary) = board.dictionaries.get(*id) else {
+                    return Err(DecodeError::UnknownDictionary(*id));
+                };
                 let uncompressed =
-                    LZ4Compression::decompress(content, CHUNK_BYTE_SIZE).map_err(|err| {
-                        let text = format!("lz4 decompression failed: {}", err);
-                        error!(text);
-                        text
-                    })?;
-                Ok(uncompressed.into())
+                    ZstdDictCompression::decompress(content, dictionary, expected_size).map_err(
+                        |source| DecodeError::Decompression {
+                            codec: ZstdDictCompression::NAME,
+                            source,
+                        },
+                    )?;
+                Self::from_packed(uncompressed, board.chunk_length, palette)
             }
-            _ => Err("Unknown compression format".into()),
+            _ => Err(DecodeError::UnknownFormat(format)),
+        }
+    }
+
+    /// Chunk of [`Board::main`] with every pixel the same color
+    pub fn filled(color: Color) -> Self {
+        let board = Board::main();
+        Self::filled_with(color, board.chunk_length, board.packing())
+    }
+
+    /// Chunk of `length` x `length` pixels with every pixel the same color
+    pub fn filled_with(color: Color, length: usize, packing: Packing) -> Self {
+        let packed = match packing {
+            Packing::Nibble => ChunkColor::new(color, color),
+            Packing::Byte => ChunkColor::single(color),
+        };
+        Self {
+            pixels: vec![packed; packing.byte_size(length * length)].into(),
+            packing,
+            stored: None,
+        }
+    }
+
+    /// The color of the chunk if every pixel has the same one
+    pub fn uniform_color(&self) -> Option<Color> {
+        let first = self.pixels[0];
+        if self.pixels.iter().any(|packed| *packed != first) {
+            return None;
         }
+        match self.packing {
+            Packing::Nibble if first.left() != first.right() => None,
+            Packing::Nibble => Some(first.left_color()),
+            Packing::Byte => Some(first.color()),
+        }
+    }
+
+    /// Nothing is painted, a missing chunk loads as this so it doesn't have to be stored
+    pub fn is_blank(&self) -> bool {
+        matches!(self.uniform_color(), Some(Color::ZERO))
     }
 
-    pub fn to_storage_bytes(self, compression: CompressionType) -> Vec<u8> {
-        let mut result = Vec::with_capacity(CHUNK_BYTE_SIZE + 1);
+    /// Encode the chunk behind a [`StorageHeader`], with the codec of the policy that gives the fewest bytes.
+    ///
+    /// Single color chunks are stored as only the color, whatever the `compression`.
+    /// When no codec makes it smaller than the chunk it's stored uncompressed.
+    pub fn to_storage_bytes(
+        self,
+        compression: &CompressionPolicy,
+        dictionaries: &Dictionaries,
+    ) -> Result<Vec<u8>, CompressionError> {
+        let uniform_color = self.uniform_color();
+        let length = self.length();
+        let packing = self.packing;
         let raw_data = self.to_u8vec();
+        let checksum = crc32fast::hash(&raw_data);
+
+        let encoded = match uniform_color {
+            Some(color) => vec![3, color.u8()],
+            None => Self::smallest_encoding(compression, dictionaries, &raw_data)?,
+        };
+
+        // the format byte becomes the codec of the header
+        let mut result = Vec::with_capacity(STORAGE_HEADER_SIZE + encoded.len() - 1);
+        StorageHeader::new(encoded[0], length, packing, checksum).write(&mut result);
+        result.extend_from_slice(&encoded[1..]);
+        Ok(result)
+    }
+
+    /// The format byte and payload of the codec giving the fewest bytes
+    fn smallest_encoding(
+        compression: &CompressionPolicy,
+        dictionaries: &Dictionaries,
+        raw_data: &[u8],
+    ) -> Result<Vec<u8>, CompressionError> {
+        let mut smallest: Option<Vec<u8>> = None;
+        for compression in compression.codecs() {
+            let encoded = Self::encode_with(*compression, dictionaries, raw_data)?;
+            if smallest
+                .as_ref()
+                .is_none_or(|smallest| encoded.len() < smallest.len())
+            {
+                smallest = Some(encoded);
+            }
+        }
+
+        match smallest {
+            // stored data of exactly LEGACY_CHUNK_BYTE_SIZE is read as the old raw format when its header is damaged
+            Some(encoded) if encoded.len() - 1 + STORAGE_HEADER_SIZE < raw_data.len() => {
+                Ok(encoded)
+            }
+            _ => Self::encode_with(CompressionType::None, dictionaries, raw_data),
+        }
+    }
+
+    fn encode_with(
+        compression: CompressionType,
+        dictionaries: &Dictionaries,
+        raw_data: &[u8],
+    ) -> Result<Vec<u8>, CompressionError> {
+        let mut result = Vec::with_capacity(raw_data.len() + 1);
 
         match compression {
             CompressionType::None => {
                 result.push(0); // no compression
-                result.extend_from_slice(&raw_data);
+                result.extend_from_slice(raw_data);
             }
-            CompressionType::Zstd => {
+            CompressionType::Zstd(level) => {
                 result.push(1); // zstd compression
-                let compressed =
-                    ZstdCompression::compress(&raw_data).expect("failed to compress with zstd");
+                let compressed = ZstdCompression::compress_with_level(raw_data, level)?;
                 result.extend_from_slice(&compressed);
             }
             CompressionType::Lz4 => {
                 result.push(2);
-                let compressed =
-                    LZ4Compression::compress(&raw_data).expect("failed to compress with zstd");
+                let compressed = LZ4Compression::compress(raw_data)?;
+                result.extend_from_slice(&compressed);
+            }
+            CompressionType::ZstdDict(id) => {
+                result.push(6);
+                result.push(id);
+                let compressed = ZstdDictCompression::compress(raw_data, dictionaries.get(id)?)?;
+                result.extend_from_slice(&compressed);
+            }
+            CompressionType::Rle => {
+                result.push(4);
+                let compressed = RleCompression::compress(raw_data)?;
+                result.extend_from_slice(&compressed);
+            }
+            CompressionType::RleZstd => {
+                result.push(5);
+                let compressed = RleZstdCompression::compress(raw_data)?;
                 result.extend_from_slice(&compressed);
             }
         }
 
-        result
+        Ok(result)
     }
 }
 
+/// The packed pixels of a square chunk, see [`Packing`]
 #[derive(Debug, Clone)]
-pub struct InnerChunk<const N: usize>(Arc<ChunkArray<N>>);
+pub struct Chunk {
+    pixels: Arc<[ChunkColor]>,
+    packing: Packing,
+    /// the stored bytes it was loaded from, dropped on the first change
+    stored: Option<Arc<[u8]>>,
+}
 
-impl<const N: usize> Deref for InnerChunk<N> {
-    type Target = ChunkArray<N>;
+impl Deref for Chunk {
+    type Target = [ChunkColor];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.pixels
     }
 }
 
-impl<const N: usize> DerefMut for InnerChunk<N> {
+impl DerefMut for Chunk {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        Arc::make_mut(&mut self.0)
+        self.stored = None;
+        Arc::make_mut(&mut self.pixels)
     }
 }
 
-impl<const N: usize> Default for InnerChunk<N> {
+/// A blank chunk of the main board
+impl Default for Chunk {
     fn default() -> Self {
-        Self(Arc::new([ChunkColor::default(); N]))
+        Self::blank(DEFAULT_CHUNK_LENGTH)
     }
 }
 
-impl<const N: usize> From<Vec<u8>> for InnerChunk<N> {
-    fn from(value: Vec<u8>) -> Self {
-        if value.len() != N {
-            panic!(
-                "Invalid size of the vector, expected {}, got {}",
-                N,
-                value.len()
-            );
-        }
-        // Convert the vector into an array of ChunkColor
-        let mut array = [ChunkColor::default(); N];
-        for (i, byte) in value.into_iter().enumerate() {
-            if let Ok(color) = byte.try_into() {
-                array[i] = color;
-            }
-        }
+/// Packed pixels of a chunk of [`Board::main`]
+impl TryFrom<Vec<u8>> for Chunk {
+    type Error = DecodeError;
 
-        Self(Arc::new(array))
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let board = Board::main();
+        Self::from_packed(value, board.chunk_length, &board.palette)
     }
 }
 
-impl<const N: usize> InnerChunk<N> {
+impl Chunk {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn new_with(data: ChunkArray<N>) -> Self {
-        InnerChunk(Arc::new(data))
+    /// Blank chunk of `length` x `length` pixels, packed like [`Board::main`]
+    pub fn blank(length: usize) -> Self {
+        Self::blank_with(length, Board::main().packing())
+    }
+
+    /// Blank chunk of `length` x `length` pixels
+    pub fn blank_with(length: usize, packing: Packing) -> Self {
+        Self {
+            pixels: vec![ChunkColor::default(); packing.byte_size(length * length)].into(),
+            packing,
+            stored: None,
+        }
+    }
+
+    /// Packed pixels of a `length` x `length` chunk, packed as the palette needs
+    pub fn from_packed(
+        data: Vec<u8>,
+        length: usize,
+        palette: &Palette,
+    ) -> Result<Self, DecodeError> {
+        let packing = palette.packing();
+        let expected = packing.byte_size(length * length);
+        if data.len() != expected {
+            return Err(DecodeError::WrongSize {
+                expected,
+                got: data.len(),
+            });
+        }
+
+        let pixels = data
+            .into_iter()
+            .map(|byte| {
+                let packed = match packing {
+                    Packing::Nibble => palette
+                        .color(byte >> 4)
+                        .zip(palette.color(byte & 0b1111))
+                        .map(|(left, right)| ChunkColor::new(left, right)),
+                    Packing::Byte => palette.color(byte).map(ChunkColor::single),
+                };
+                packed.ok_or(DecodeError::InvalidColor(byte))
+            })
+            .collect::<Result<Arc<[ChunkColor]>, _>>()?;
+
+        Ok(Self {
+            pixels,
+            packing,
+            stored: None,
+        })
+    }
+
+    /// Pixels in a row, and rows in the chunk
+    pub fn length(&self) -> usize {
+        (self.pixels.len() * 8 / self.packing.bits()).isqrt()
+    }
+
+    pub fn packing(&self) -> Packing {
+        self.packing
+    }
+
+    /// The stored bytes, with their [`StorageHeader`], while it's unchanged since it was loaded
+    pub fn stored_bytes(&self) -> Option<&[u8]> {
+        self.stored.as_deref()
+    }
+
+    /// The format byte and payload of [`Chunk::stored_bytes`]
+    pub fn stored_encoding(&self) -> Option<(u8, &[u8])> {
+        let stored = self.stored.as_deref()?;
+        let header = StorageHeader::parse(stored)?.ok()?;
+        Some((header.codec, &stored[STORAGE_HEADER_SIZE..]))
     }
 
     pub fn to_vec(self) -> Vec<ChunkColor> {
-        self.0.deref().into()
+        self.pixels.deref().into()
     }
 
     // ? let's pray that this gets optimized out
     pub fn to_u8vec(self) -> Vec<u8> {
-        self.0.iter().map(|&color| color.0).collect()
+        self.pixels.iter().map(|&color| color.0).collect()
 
         // or we just do unsafe, how scary.
         // ? this will fail when ChunkColor is not u8 anymore tho
         // let slice: &[u8] =
-        //     unsafe { std::slice::from_raw_parts(self.0.as_ptr() as *const u8, self.0.len()) };
+        //     unsafe { std::slice::from_raw_parts(self.pixels.as_ptr() as *const u8, self.pixels.len()) };
         // slice.to_vec()
     }
 
-    /// Set a pixel color at a packed index (0 to CHUNK_SIZE-1)
+    /// Get the pixel color at a packed index, below the pixels of the chunk
+    pub fn pixel(&self, packed_index: usize) -> Color {
+        if self.packing == Packing::Byte {
+            return self.pixels[packed_index].color();
+        }
+
+        let chunk_color = self.pixels[packed_index / 2];
+        if packed_index & 1 == 0 {
+            chunk_color.left_color()
+        } else {
+            chunk_color.right_color()
+        }
+    }
+
+    /// Set a pixel color at a packed index, below the pixels of the chunk
     pub fn set_pixel(&mut self, packed_index: usize, color: Color) {
-        if packed_index >= CHUNK_SIZE {
+        self.stored = None;
+        if self.packing == Packing::Byte {
+            if packed_index < self.pixels.len() {
+                Arc::make_mut(&mut self.pixels)[packed_index] = ChunkColor::single(color);
+            }
             return;
         }
 
         let byte_index = packed_index / 2;
         let is_left = packed_index & 1 == 0;
 
-        if byte_index >= N {
+        if byte_index >= self.pixels.len() {
             return;
         }
 
-        let chunk_array = Arc::make_mut(&mut self.0);
+        let chunk_array = Arc::make_mut(&mut self.pixels);
         if is_left {
             chunk_array[byte_index].set_left(color);
         } else {
@@ -427,9 +1267,9 @@ impl<const N: usize> InnerChunk<N> {
     }
 }
 
-impl<const N: usize> Into<Vec<u8>> for InnerChunk<N> {
-    fn into(self) -> Vec<u8> {
-        self.to_u8vec()
+impl From<Chunk> for Vec<u8> {
+    fn from(val: Chunk) -> Self {
+        val.to_u8vec()
     }
 }
 
@@ -437,33 +1277,33 @@ impl<const N: usize> Into<Vec<u8>> for InnerChunk<N> {
 // pub type Board = Arc<RwLock<Chunk>>;
 
 /// Alway valid coordinates of a chunk
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct ChunkCoordinates {
     x: i64,
     y: i64,
 }
 
-impl Default for ChunkCoordinates {
-    fn default() -> Self {
-        Self {
-            x: Default::default(),
-            y: Default::default(),
-        }
-    }
+#[derive(thiserror::Error, Debug)]
+#[error("coordinates ({x}, {y}) are outside of the board")]
+pub struct OutOfBoundsError {
+    x: i64,
+    y: i64,
 }
 
 impl ChunkCoordinates {
-    pub fn new(x: i64, y: i64) -> Result<Self, ()> {
-        // check if the values are valid, chunks_in_direction is usize.
-
-        let chunks_in_direction = *CHUNKS_IN_DIRECTION as i64;
+    /// Coordinates on [`Board::main`], see [`Board::coordinates`] for a configured board
+    pub fn new(x: i64, y: i64) -> Result<Self, OutOfBoundsError> {
+        Self::within(x, y, &Board::main().bounds())
+    }
 
-        if x.abs() > chunks_in_direction || y.abs() > chunks_in_direction {
+    /// Coordinates on a board with these bounds
+    pub fn within(x: i64, y: i64, bounds: &Bounds) -> Result<Self, OutOfBoundsError> {
+        if !bounds.contains(x, y) {
             debug!(
-                "Invalid coordinates, x: {}, y: {}, chunks_in_direction: {}",
-                x, y, chunks_in_direction
+                "Invalid coordinates, x: {}, y: {}, bounds: {:?}",
+                x, y, bounds
             );
-            return Err(());
+            return Err(OutOfBoundsError { x, y });
         }
 
         Ok(Self { x, y })
@@ -480,13 +1320,26 @@ impl ChunkCoordinates {
     pub fn object_name(&self) -> String {
         format!("{}_{}.chunk", self.x, self.y)
     }
+
+    /// `x` and `y` of an [`object_name`](ChunkCoordinates::object_name), which might be outside of the board
+    pub fn parse_object_name(name: &str) -> Option<(i64, i64)> {
+        let (x, y) = name.strip_suffix(".chunk")?.split_once('_')?;
+        Some((x.parse().ok()?, y.parse().ok()?))
+    }
 }
 
 use serde::{Deserialize, Serialize};
 
+use crate::compression::ChunkCompression;
 use crate::compression::Compression;
+use crate::compression::CompressionError;
+use crate::compression::DEFAULT_ZSTD_LEVEL;
+use crate::compression::Dictionaries;
 use crate::compression::LZ4Compression;
+use crate::compression::RleCompression;
+use crate::compression::RleZstdCompression;
 use crate::compression::ZstdCompression;
+use crate::compression::ZstdDictCompression;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CellChangeMessage {
@@ -496,48 +1349,73 @@ pub struct CellChangeMessage {
 
 /// packed cell is an index and value packed into a u64
 ///
-/// index is 60 bits, value is 4 bits
+/// index is 60 bits, value is 4 bits.
+/// With [`Packing::Byte`] the value is 8 bits, and the index 56.
+///
+/// Clients that ask for it get them in fewer bytes, see [`CellEncoding::Compact`].
 ///
-/// * this could be less thas u64
 /// ! change the case 2 of index.html when changeing the size
-#[derive(Debug, Clone)]
-pub struct PackedCell(u64);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackedCell {
+    index: usize,
+    color: Color,
+}
 
 impl PackedCell {
+    /// A cell of the main board
     pub fn new(index: usize, value: u8) -> Option<Self> {
-        if index >= CHUNK_SIZE {
+        Self::on_board(index, value, &Board::main())
+    }
+
+    /// `None` when the index is outside of a chunk of the board, or its palette has no such color
+    pub fn on_board(index: usize, value: u8, board: &Board) -> Option<Self> {
+        if index >= board.chunk_size() {
             return None;
         }
-        if let Some(color) = Color::new(value) {
-            Some(PackedCell(((index as u64) << 4) | (color.u8() as u64)))
-        } else {
-            None
-        }
+        board
+            .palette
+            .color(value)
+            .map(|color| PackedCell { index, color })
     }
 
+    /// A cell of the main board, as sent by a client
     pub fn new_from_u64(packed_value: u64) -> Option<Self> {
-        let index = (packed_value >> 4) as usize;
-        // last 58 bits are the color
-        let value = (packed_value & 0xF) as u8;
+        Self::from_u64(packed_value, &Board::main())
+    }
+
+    /// A cell of the board, the color has as many bits as a pixel in its chunks
+    pub fn from_u64(packed_value: u64, board: &Board) -> Option<Self> {
+        let bits = board.packing().bits();
+        let index = (packed_value >> bits) as usize;
+        // the rest of the bits are the color
+        let value = (packed_value & ((1 << bits) - 1)) as u8;
 
-        Self::new(index, value)
+        Self::on_board(index, value, board)
     }
 
     pub fn index(&self) -> usize {
-        (self.0 >> 4) as usize
+        self.index
     }
 
     pub fn value(&self) -> u8 {
-        (self.0 & 0xF) as u8
+        self.color.u8()
     }
 
-    // we know that the value is a valid color
     pub fn color(&self) -> Color {
-        Color::new(self.value()).unwrap()
+        self.color
     }
 
+    pub fn to_u64(&self, packing: Packing) -> u64 {
+        ((self.index as u64) << packing.bits()) | self.color.u8() as u64
+    }
+
+    /// The little endian u64 of a cell of [`Board::main`]
     pub fn to_binary(&self) -> [u8; 8] {
-        self.0.to_le_bytes()
+        self.to_binary_with(Board::main().packing())
+    }
+
+    pub fn to_binary_with(&self, packing: Packing) -> [u8; 8] {
+        self.to_u64(packing).to_le_bytes()
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
@@ -545,20 +1423,238 @@ impl PackedCell {
     }
 }
 
+/// How the cells of updates are written on a websocket, both ways, chosen per connection
+///
+/// Clients ask for one with `?cells=compact` on the websocket url,
+/// the [`WsMessage::ChunkDimensions`] message tells which one they got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellEncoding {
+    /// A little endian u64 per cell, see [`PackedCell::to_u64`]
+    #[default]
+    Wide,
+    /// LEB128 varint records of `(cell << 1) | run`, with the cell as in [`PackedCell::to_u64`].
+    ///
+    /// A run is followed by a varint of its length, and paints that many cells from
+    /// the index on in its color. A cell of a 100x100 chunk with 16 colors takes 3 bytes.
+    Compact,
+}
+
+impl CellEncoding {
+    /// How the encoding is named in the [`WsMessage::ChunkDimensions`] message
+    pub fn id(&self) -> u8 {
+        match self {
+            CellEncoding::Wide => 0,
+            CellEncoding::Compact => 1,
+        }
+    }
+
+    pub fn encode(&self, updates: &[PackedCell], packing: Packing, buffer: &mut Vec<u8>) {
+        match self {
+            CellEncoding::Wide => {
+                for update in updates {
+                    buffer.extend_from_slice(&update.to_binary_with(packing));
+                }
+            }
+            CellEncoding::Compact => {
+                let mut rest = updates;
+                while let Some(first) = rest.first() {
+                    // the same color on the following indices
+                    let run = rest
+                        .iter()
+                        .zip(0..)
+                        .take_while(|(cell, offset)| {
+                            cell.color == first.color && cell.index == first.index + offset
+                        })
+                        .count();
+
+                    let record = first.to_u64(packing) << 1;
+                    if run > 1 {
+                        write_varint(buffer, record | 1);
+                        write_varint(buffer, run as u64);
+                    } else {
+                        write_varint(buffer, record);
+                    }
+                    rest = &rest[run..];
+                }
+            }
+        }
+    }
+
+    /// The cells of a frame from a client to the board, invalid cells are left out
+    pub fn decode(&self, data: &[u8], board: &Board) -> Vec<PackedCell> {
+        match self {
+            CellEncoding::Wide => data
+                .chunks_exact(8)
+                .filter_map(|chunk| {
+                    let eight_arr: [u8; 8] = chunk.try_into().unwrap();
+
+                    // in 8 bytes, we have the index and the value.
+                    match u64::from_le_bytes(eight_arr) {
+                        0 => None,
+                        packed_value => PackedCell::from_u64(packed_value, board),
+                    }
+                })
+                .collect(),
+            CellEncoding::Compact => {
+                let chunk_size = board.chunk_size();
+                let mut cells = Vec::new();
+                let mut data = data;
+                // a cut off record ends the frame, as does a frame painting more than a chunk
+                while cells.len() < chunk_size
+                    && let Some(record) = read_varint(&mut data)
+                {
+                    let length = match record & 1 {
+                        1 => match read_varint(&mut data) {
+                            Some(length) => length,
+                            None => break,
+                        },
+                        _ => 1,
+                    };
+
+                    let Some(first) = PackedCell::from_u64(record >> 1, board) else {
+                        continue;
+                    };
+                    // a run doesn't reach outside of the chunk
+                    let length = length
+                        .min((chunk_size - first.index) as u64)
+                        .min((chunk_size - cells.len()) as u64)
+                        as usize;
+                    cells.extend((0..length).map(|offset| PackedCell {
+                        index: first.index + offset,
+                        color: first.color,
+                    }));
+                }
+                cells
+            }
+        }
+    }
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Reads a varint from the front of `data`, `None` when it is cut off or longer than a u64
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (position, byte) in data.iter().enumerate().take(10) {
+        value |= ((byte & 0x7F) as u64) << (7 * position);
+        if byte & 0x80 == 0 {
+            *data = &data[position + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Format bytes of stored chunks a websocket client can decode, `?formats=0,3,4`
+///
+/// Formats the server doesn't know are left out, as is 6 as the client has no dictionaries.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ChunkFormats(Vec<u8>);
+
+impl std::str::FromStr for ChunkFormats {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let formats = value
+            .split(',')
+            .filter(|format| !format.is_empty())
+            .map(|format| {
+                format
+                    .trim()
+                    .parse::<u8>()
+                    .map_err(|_| format!("invalid format byte {:?}", format))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self(
+            formats.into_iter().filter(|format| *format < 6).collect(),
+        ))
+    }
+}
+
+impl TryFrom<String> for ChunkFormats {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl ChunkFormats {
+    pub fn contains(&self, format: u8) -> bool {
+        self.0.contains(&format)
+    }
+
+    /// The format byte and payload of the chunk in one of the formats, the fewest bytes of them.
+    ///
+    /// The stored bytes are used as they are when the chunk didn't change since it was loaded.
+    /// `None` when the client takes none of them.
+    pub fn encode(&self, chunk: &Chunk) -> Option<Vec<u8>> {
+        if let Some((format, payload)) = chunk
+            .stored_encoding()
+            .filter(|(format, _)| self.contains(*format))
+        {
+            let mut encoded = Vec::with_capacity(payload.len() + 1);
+            encoded.push(format);
+            encoded.extend_from_slice(payload);
+            return Some(encoded);
+        }
+
+        if let Some(color) = chunk.uniform_color().filter(|_| self.contains(3)) {
+            return Some(vec![3, color.u8()]);
+        }
+
+        let raw_data = chunk.data();
+        self.0
+            .iter()
+            .filter_map(|format| match format {
+                0 => Some(CompressionType::None),
+                1 => Some(CompressionType::Zstd(DEFAULT_ZSTD_LEVEL)),
+                2 => Some(CompressionType::Lz4),
+                4 => Some(CompressionType::Rle),
+                5 => Some(CompressionType::RleZstd),
+                _ => None,
+            })
+            .filter(|compression| compression.supports(chunk.packing()))
+            // clients have no dictionaries
+            .filter_map(|compression| {
+                Chunk::encode_with(compression, &Dictionaries::default(), &raw_data).ok()
+            })
+            .min_by_key(Vec::len)
+    }
+}
+
 pub enum WsMessage {
     EntireChunk,
     ChunkUpdate,
     ChunkNotFound,
     TooManyChunksLoaded,
+    /// Width, height and bits per pixel of the chunks, sent before the first chunk
+    ChunkDimensions,
+    /// The entire chunk as a format byte and payload, see [`ChunkFormats`]
+    EncodedChunk,
+    /// Whether updates of the chunk are dropped, because its stored data couldn't be loaded
+    ReadOnly,
 }
 
-impl Into<u8> for WsMessage {
-    fn into(self) -> u8 {
-        match self {
+impl From<WsMessage> for u8 {
+    fn from(val: WsMessage) -> Self {
+        match val {
             WsMessage::EntireChunk => 1,
             WsMessage::ChunkUpdate => 2,
             WsMessage::ChunkNotFound => 3,
             WsMessage::TooManyChunksLoaded => 4,
+            WsMessage::ChunkDimensions => 5,
+            WsMessage::EncodedChunk => 6,
+            WsMessage::ReadOnly => 7,
         }
     }
 }
@@ -572,18 +1668,54 @@ impl WsMessage {
         vec![WsMessage::ChunkNotFound.into()]
     }
 
-    pub fn chunk_update_buffer(updates: Vec<PackedCell>) -> Vec<u8> {
+    /// 1 when the chunk became read only, 0 when it can be painted again
+    pub fn read_only_buffer(read_only: bool) -> Vec<u8> {
+        vec![WsMessage::ReadOnly.into(), read_only as u8]
+    }
+
+    /// Width and height as little endian u16, followed by the bits per pixel
+    /// and the [`CellEncoding::id`] of the connection
+    pub fn chunk_dimensions_buffer(
+        length: usize,
+        packing: Packing,
+        encoding: CellEncoding,
+    ) -> Vec<u8> {
+        let mut buffer = vec![WsMessage::ChunkDimensions.into()];
+        buffer.extend_from_slice(&(length as u16).to_le_bytes());
+        buffer.extend_from_slice(&(length as u16).to_le_bytes());
+        buffer.push(packing.bits() as u8);
+        buffer.push(encoding.id());
+        buffer
+    }
+
+    /// The updates in the encoding of the connection, the colors with as many bits as `packing` has
+    pub fn chunk_update_buffer(
+        updates: &[PackedCell],
+        packing: Packing,
+        encoding: CellEncoding,
+    ) -> Vec<u8> {
         let mut buffer = Vec::with_capacity(updates.len() * 8 + 1);
         buffer.push(WsMessage::ChunkUpdate.into());
-        for update in updates {
-            buffer.extend_from_slice(&update.to_binary());
-        }
+        encoding.encode(updates, packing, &mut buffer);
         buffer
     }
     pub fn entire_chunk_buffer(chunk: Chunk) -> Vec<u8> {
-        let mut buffer = Vec::with_capacity(CHUNK_BYTE_SIZE + 1);
+        let mut buffer = Vec::with_capacity(chunk.len() + 1);
         buffer.push(WsMessage::EntireChunk.into());
         buffer.extend_from_slice(&chunk.to_u8vec());
         buffer
     }
+
+    /// The chunk in a format the client takes, the packed pixels when it takes none
+    pub fn chunk_buffer(chunk: Chunk, formats: &ChunkFormats) -> Vec<u8> {
+        match formats.encode(&chunk) {
+            Some(encoded) => {
+                let mut buffer = Vec::with_capacity(encoded.len() + 1);
+                buffer.push(WsMessage::EncodedChunk.into());
+                buffer.extend_from_slice(&encoded);
+                buffer
+            }
+            None => Self::entire_chunk_buffer(chunk),
+        }
+    }
 }
@@ -1,6 +1,6 @@
 use axum::{
     extract::{
-        Path, State, WebSocketUpgrade,
+        Path, Query, State, WebSocketUpgrade,
         ws::{Message, WebSocket},
     },
     response::IntoResponse,
@@ -9,30 +9,47 @@ use futures::{
     sink::SinkExt,
     stream::{SplitSink, SplitStream, StreamExt},
 };
+use serde::Deserialize;
 use tracing::{debug, info};
 
 use crate::board_manager;
-use crate::{AppState, chunk_manager};
+use crate::router::{ChunkPath, CurrentBoard};
+use crate::{AppState, BoardHandle, chunk_manager};
 
 use paintplayground::types::*;
 
-#[axum::debug_handler]
+#[derive(Deserialize)]
+pub struct WsQuery {
+    /// how the client wants its updates, older clients only know [`CellEncoding::Wide`]
+    #[serde(default)]
+    cells: CellEncoding,
+    /// how the client can take entire chunks, older clients only the packed pixels
+    #[serde(default)]
+    formats: ChunkFormats,
+}
+
+#[axum::debug_handler(state = AppState)]
 pub async fn ws_handler(
-    Path((x, y)): Path<(i64, i64)>,
+    Path(ChunkPath { x, y }): Path<ChunkPath>,
+    Query(query): Query<WsQuery>,
+    CurrentBoard(board): CurrentBoard,
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
     // todo, check if the user is allowed to connect to this chunk
-    let Ok(coordinates) = ChunkCoordinates::new(x, y) else {
+    let Ok(coordinates) = board.board.coordinates(x, y) else {
         return axum::http::StatusCode::NOT_FOUND.into_response();
     };
 
     // upgrade the request to a websocket
-    ws.on_upgrade(move |socket| handle_socket(socket, coordinates, state))
+    ws.on_upgrade(move |socket| handle_socket(socket, coordinates, query, board, state))
 }
 
 struct WebSocketHandler {
     _coordinates: ChunkCoordinates,
+    board: Arc<Board>,
+    encoding: CellEncoding,
+    formats: ChunkFormats,
     handler_data: chunk_manager::HandlerData,
     // broadcast_rx: broadcast::Receiver<Vec<PackedCell>>,
     // update_tx: mpsc::Sender<Vec<PackedCell>>,
@@ -45,20 +62,29 @@ struct WebSocketHandler {
 impl WebSocketHandler {
     // possibly returns websocket to be able to do stuff with it
     async fn connect(
-        state: &AppState,
+        board: &BoardHandle,
         mut socket: WebSocket,
         coordinates: ChunkCoordinates,
+        WsQuery {
+            cells: encoding,
+            formats,
+        }: WsQuery,
     ) -> Result<Self, WebSocket> {
         // try to get the chunk
         debug!("WH - getting handler data");
-        let handler_data = match state.board_communicator.get_handler(coordinates).await {
+        let mut handler_data = match board.board_communicator.get_handler(coordinates).await {
             Err(err) => match err {
                 board_manager::BoardManagerError::TooManyChunksLoaded => {
                     let message = WsMessage::too_many_chunks_buffer();
                     socket.send(Message::Binary(message.into())).await.unwrap();
                     return Err(socket);
                 }
-                board_manager::BoardManagerError::_LoadingChunks => {
+                board_manager::BoardManagerError::_LoadingChunks
+                | board_manager::BoardManagerError::Unresponsive
+                | board_manager::BoardManagerError::StorageUnreachable(_)
+                | board_manager::BoardManagerError::Storage(_)
+                | board_manager::BoardManagerError::Resetting
+                | board_manager::BoardManagerError::Shrink(_) => {
                     let message = WsMessage::chunk_not_found_buffer();
                     socket.send(Message::Binary(message.into())).await.unwrap();
                     return Err(socket);
@@ -75,15 +101,29 @@ impl WebSocketHandler {
         //     .await;
         let chunk = handler_data.fetch_chunk().await;
 
+        // the client needs the size and packing of the chunks to place the pixels,
+        // and the encoding to read the updates
+        let message = WsMessage::chunk_dimensions_buffer(chunk.length(), chunk.packing(), encoding);
+        socket.send(Message::Binary(message.into())).await.unwrap();
+
         // send the chunk to the client
         debug!("sending chunk to client");
-        let message = WsMessage::entire_chunk_buffer(chunk);
+        let message = WsMessage::chunk_buffer(chunk, &formats);
         socket.send(Message::Binary(message.into())).await.unwrap();
 
+        // later changes are sent by the sender
+        if *handler_data.read_only_rx.borrow_and_update() {
+            let message = WsMessage::read_only_buffer(true);
+            socket.send(Message::Binary(message.into())).await.unwrap();
+        }
+
         let (sender, receiver) = socket.split();
 
         Ok(Self {
             _coordinates: coordinates,
+            board: board.board.clone(),
+            encoding,
+            formats,
             handler_data,
             // broadcast_rx: handler_data.broadcast_rx,
             // update_tx: handler_data.update_tx,
@@ -93,8 +133,20 @@ impl WebSocketHandler {
     }
 
     async fn run(self) {
-        let mut receiver_handler = Self::start_receiver(self.receiver, self.handler_data.update_tx);
-        let mut sender_handler = Self::start_sender(self.sender, self.handler_data.broadcast_rx);
+        let mut receiver_handler = Self::start_receiver(
+            self.receiver,
+            self.handler_data.update_tx,
+            self.board.clone(),
+            self.encoding,
+        );
+        let mut sender_handler = Self::start_sender(
+            self.sender,
+            self.handler_data.broadcast_rx,
+            self.handler_data.read_only_rx,
+            self.board,
+            self.encoding,
+            self.formats,
+        );
 
         tokio::select! {
             _ = &mut receiver_handler => {
@@ -106,12 +158,13 @@ impl WebSocketHandler {
                 receiver_handler.abort();
             }
         }
-        return;
     }
 
     fn start_receiver(
         mut receiver: SplitStream<WebSocket>,
         update_tx: mpsc::Sender<Vec<PackedCell>>,
+        board: Arc<Board>,
+        encoding: CellEncoding,
     ) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
             while let Some(msg) = receiver.next().await {
@@ -128,19 +181,7 @@ impl WebSocketHandler {
                         // todo, add first byte for message type.
 
                         // messages will be an array of index and value (PackedCell)
-                        let updates: Vec<PackedCell> = data
-                            .chunks_exact(8)
-                            .map(|chunk| {
-                                let eight_arr: [u8; 8] = chunk.try_into().unwrap();
-
-                                // in 8 bytes, we have the index and the value.
-                                match u64::from_le_bytes(eight_arr) {
-                                    0 => None,
-                                    packed_value => PackedCell::new_from_u64(packed_value),
-                                }
-                            })
-                            .filter_map(|x| x)
-                            .collect();
+                        let updates = encoding.decode(&data, &board);
 
                         debug!("received {} updates", updates.len());
                         update_tx.send(updates).await.unwrap();
@@ -160,48 +201,76 @@ impl WebSocketHandler {
 
     /// Receive messages from the [`ChunkManager`](crate::chunk_manager::ChunkManager) and send them to the client
     ///
-    /// The messages will be the buffered changes
+    /// The messages will be the buffered changes, the entire chunk after a reset,
+    /// or the chunk becoming read only or writable
     fn start_sender(
         mut sender: SplitSink<WebSocket, Message>,
-        mut broadcast_rx: broadcast::Receiver<Vec<PackedCell>>,
+        mut broadcast_rx: broadcast::Receiver<chunk_manager::ChunkBroadcast>,
+        mut read_only_rx: tokio::sync::watch::Receiver<bool>,
+        board: Arc<Board>,
+        encoding: CellEncoding,
+        formats: ChunkFormats,
     ) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
             loop {
-                match broadcast_rx.recv().await {
-                    Ok(packed_cells) => {
-                        debug!("received broadcast");
-                        let message = WsMessage::chunk_update_buffer(packed_cells);
-
-                        match sender.send(Message::Binary(message.into())).await {
-                            Ok(_) => (), // message got send fine,
-                            Err(err) => {
-                                // something broke the pipe, most likely the connection was closed in between await operations
-                                error!("sender could not send {}", err);
-                                break;
+                let message = tokio::select! {
+                    broadcast = broadcast_rx.recv() => match broadcast {
+                        Ok(broadcast) => {
+                            debug!("received broadcast");
+                            match broadcast {
+                                chunk_manager::ChunkBroadcast::Updates(packed_cells) => {
+                                    WsMessage::chunk_update_buffer(
+                                        &packed_cells,
+                                        board.packing(),
+                                        encoding,
+                                    )
+                                }
+                                chunk_manager::ChunkBroadcast::EntireChunk(chunk) => {
+                                    WsMessage::chunk_buffer(chunk, &formats)
+                                }
                             }
-                        };
+                        }
+                        Err(e) => {
+                            debug!("error receiving message: {:?}", e);
+                            // The ChunkManager has been dropped, close the connection
+                            let _ = sender
+                                .send(Message::Close(None))
+                                .await
+                                .map_err(|err| error!("could not send close message {}", err));
+
+                            break;
+                        }
+                    },
+                    // stops when the ChunkManager is dropped, the broadcast handles that
+                    Ok(()) = read_only_rx.changed() => {
+                        WsMessage::read_only_buffer(*read_only_rx.borrow_and_update())
                     }
-                    Err(e) => {
-                        debug!("error receiving message: {:?}", e);
-                        // The ChunkManager has been dropped, close the connection
-                        let _ = sender
-                            .send(Message::Close(None))
-                            .await
-                            .map_err(|err| error!("could not send close message {}", err));
+                };
 
+                match sender.send(Message::Binary(message.into())).await {
+                    Ok(_) => (), // message got send fine,
+                    Err(err) => {
+                        // something broke the pipe, most likely the connection was closed in between await operations
+                        error!("sender could not send {}", err);
                         break;
                     }
-                }
+                };
             }
         })
     }
 }
 
-async fn handle_socket(socket: WebSocket, coordinates: ChunkCoordinates, state: AppState) {
+async fn handle_socket(
+    socket: WebSocket,
+    coordinates: ChunkCoordinates,
+    query: WsQuery,
+    board: BoardHandle,
+    state: AppState,
+) {
     state.add_connection();
 
     debug!("new websocket connection");
-    let handler = WebSocketHandler::connect(&state, socket, coordinates).await;
+    let handler = WebSocketHandler::connect(&board, socket, coordinates, query).await;
 
     match handler {
         Ok(handler) => {